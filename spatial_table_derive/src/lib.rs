@@ -0,0 +1,383 @@
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Fields, GenericArgument, Ident, Path,
+    PathArguments, Type, TypePath,
+};
+
+/// Implements `spatial_table::Layers` for a struct whose fields are each an
+/// `Option<spatial_table::Entity>`, and generates an enum with a variant for each field and a
+/// generic table with a field of an arbitrary type for each layer. The generated items have the
+/// same visibility as the struct and are placed alongside it.
+///
+/// Options on the struct, in `#[layers(...)]`:
+/// - `layer = Name`: the name of the generated enum (default `Layer`)
+/// - `table = Name`: the name of the generated table (default `LayerTable`)
+/// - `set = Name`: the name of the generated alias for `spatial_table::LayerSet` (default
+///   `LayerSet`)
+/// - `derive(...)`: extra derives for the generated enum
+/// - `serde`: serialize the generated enum by layer name (requires the `serialize` feature of
+///   `spatial_table`)
+/// - `crate = path`: the path to the `spatial_table` crate (default `::spatial_table`)
+///
+/// Options on each field, in `#[layer(...)]`:
+/// - `stack`: the layer is a stack layer
+/// - `variant = Name`: the name of the enum variant (default is the field name in
+///   UpperCamelCase)
+///
+/// Doc comments on fields are copied to the corresponding enum variants and table fields. Other
+/// field attributes are left on the struct.
+#[proc_macro_derive(Layers, attributes(layers, layer))]
+pub fn derive_layers(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+struct Options {
+    layer: Ident,
+    table: Ident,
+    set: Ident,
+    derives: Vec<Path>,
+    serde: bool,
+    krate: Path,
+}
+
+struct Field {
+    ident: Ident,
+    variant: Ident,
+    stack: bool,
+    docs: Vec<Attribute>,
+}
+
+fn upper_camel_case(ident: &Ident) -> Ident {
+    let name = ident
+        .to_string()
+        .split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                None => String::new(),
+            }
+        })
+        .collect::<String>();
+    Ident::new(&name, ident.span())
+}
+
+fn parse_options(attrs: &[Attribute]) -> syn::Result<Options> {
+    let mut options = Options {
+        layer: Ident::new("Layer", Span::call_site()),
+        table: Ident::new("LayerTable", Span::call_site()),
+        set: Ident::new("LayerSet", Span::call_site()),
+        derives: Vec::new(),
+        serde: false,
+        krate: syn::parse_quote!(::spatial_table),
+    };
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("layers")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("layer") {
+                options.layer = meta.value()?.parse()?;
+            } else if meta.path.is_ident("table") {
+                options.table = meta.value()?.parse()?;
+            } else if meta.path.is_ident("set") {
+                options.set = meta.value()?.parse()?;
+            } else if meta.path.is_ident("crate") {
+                options.krate = meta.value()?.parse()?;
+            } else if meta.path.is_ident("serde") {
+                options.serde = true;
+            } else if meta.path.is_ident("derive") {
+                meta.parse_nested_meta(|meta| {
+                    options.derives.push(meta.path);
+                    Ok(())
+                })?;
+            } else {
+                return Err(meta.error("unrecognised layers option"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(options)
+}
+
+/// Returns `true` iff `ty` is written as `Option<Entity>`, allowing either type to be given by
+/// a path
+fn is_option_entity(ty: &Type) -> bool {
+    let last_segment = |ty: &Type| match ty {
+        Type::Path(TypePath { qself: None, path }) => path.segments.last().cloned(),
+        _ => None,
+    };
+    let Some(option) = last_segment(ty) else {
+        return false;
+    };
+    if option.ident != "Option" {
+        return false;
+    }
+    match option.arguments {
+        PathArguments::AngleBracketed(arguments) if arguments.args.len() == 1 => {
+            match &arguments.args[0] {
+                GenericArgument::Type(ty) => last_segment(ty)
+                    .is_some_and(|entity| entity.ident == "Entity" && entity.arguments.is_none()),
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+fn parse_field(field: &syn::Field) -> syn::Result<Field> {
+    let ident = field.ident.clone().expect("fields are named");
+    if !is_option_entity(&field.ty) {
+        return Err(syn::Error::new_spanned(
+            &field.ty,
+            "each field of a Layers struct must be an `Option<Entity>`",
+        ));
+    }
+    let mut variant = upper_camel_case(&ident);
+    let mut stack = false;
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("layer"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("stack") {
+                stack = true;
+            } else if meta.path.is_ident("variant") {
+                variant = meta.value()?.parse()?;
+            } else {
+                return Err(meta.error("unrecognised layer option"));
+            }
+            Ok(())
+        })?;
+    }
+    let docs = field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .cloned()
+        .collect();
+    Ok(Field {
+        ident,
+        variant,
+        stack,
+        docs,
+    })
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "Layers can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "Layers can only be derived for structs",
+            ))
+        }
+    };
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "Layers can't be derived for generic structs",
+        ));
+    }
+    let fields = fields
+        .iter()
+        .map(parse_field)
+        .collect::<syn::Result<Vec<_>>>()?;
+    let Options {
+        layer,
+        table,
+        set,
+        derives,
+        serde,
+        krate,
+    } = parse_options(&input.attrs)?;
+    let vis = &input.vis;
+    let name = &input.ident;
+    let idents = fields.iter().map(|f| &f.ident).collect::<Vec<_>>();
+    let variants = fields.iter().map(|f| &f.variant).collect::<Vec<_>>();
+    let docs = fields.iter().map(|f| &f.docs).collect::<Vec<_>>();
+    let stacks = fields.iter().map(|f| f.stack).collect::<Vec<_>>();
+    let serde_impls = if serde {
+        quote! {
+            impl #krate::serde::Serialize for #layer {
+                fn serialize<S: #krate::serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                    s.serialize_str(self.name())
+                }
+            }
+
+            impl<'a> #krate::serde::Deserialize<'a> for #layer {
+                fn deserialize<D: #krate::serde::Deserializer<'a>>(d: D) -> Result<Self, D::Error> {
+                    let name = <String as #krate::serde::Deserialize>::deserialize(d)?;
+                    name.parse().map_err(<D::Error as #krate::serde::de::Error>::custom)
+                }
+            }
+        }
+    } else {
+        quote!()
+    };
+    Ok(quote! {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, #(#derives),*)]
+        #vis enum #layer {
+            #(#(#docs)* #variants,)*
+        }
+
+        impl #layer {
+            /// The number of layers
+            #[allow(unused)]
+            #vis const COUNT: usize = [#(#layer::#variants,)*].len();
+
+            /// Every layer, in the order they were declared
+            #[allow(unused)]
+            #vis const ALL: [#layer; Self::COUNT] = [#(#layer::#variants,)*];
+
+            /// The position of the layer in [`Self::ALL`]
+            #[allow(unused)]
+            #vis fn index(self) -> usize {
+                self as usize
+            }
+
+            #[allow(unused)]
+            #vis fn from_index(index: usize) -> Option<Self> {
+                Self::ALL.get(index).copied()
+            }
+
+            #[allow(unused)]
+            #vis fn name(self) -> &'static str {
+                match self {
+                    #(#layer::#variants => stringify!(#variants),)*
+                }
+            }
+
+            #[allow(unused)]
+            #vis fn from_name(name: &str) -> Option<Self> {
+                match name {
+                    #(stringify!(#variants) => Some(#layer::#variants),)*
+                    _ => None,
+                }
+            }
+        }
+
+        impl ::std::fmt::Display for #layer {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                f.write_str(self.name())
+            }
+        }
+
+        impl ::std::str::FromStr for #layer {
+            type Err = #krate::ParseLayerError;
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                Self::from_name(s).ok_or_else(|| #krate::ParseLayerError(s.to_string()))
+            }
+        }
+
+        #serde_impls
+
+        #vis type #set = #krate::LayerSet<#name>;
+
+        impl From<#layer> for #krate::LayerSet<#name> {
+            fn from(layer: #layer) -> Self {
+                Self::single(layer)
+            }
+        }
+
+        impl ::std::ops::BitOr for #layer {
+            type Output = #krate::LayerSet<#name>;
+            fn bitor(self, rhs: #layer) -> Self::Output {
+                #krate::LayerSet::single(self).with(rhs)
+            }
+        }
+
+        impl ::std::ops::BitOr<#layer> for #krate::LayerSet<#name> {
+            type Output = Self;
+            fn bitor(self, rhs: #layer) -> Self {
+                self.with(rhs)
+            }
+        }
+
+        impl #krate::Layers for #name {
+            type Layer = #layer;
+            const LAYERS: &'static [#layer] = &#layer::ALL;
+            fn select_field(&self, layer: Self::Layer) -> &Option<#krate::Entity> {
+                match layer {
+                    #(#layer::#variants => &self.#idents,)*
+                }
+            }
+            fn select_field_mut(&mut self, layer: Self::Layer) -> &mut Option<#krate::Entity> {
+                match layer {
+                    #(#layer::#variants => &mut self.#idents,)*
+                }
+            }
+            fn layer_index(layer: Self::Layer) -> usize {
+                layer.index()
+            }
+            fn layer_name(layer: Self::Layer) -> &'static str {
+                layer.name()
+            }
+            fn is_stack(layer: Self::Layer) -> bool {
+                match layer {
+                    #(#layer::#variants => #stacks,)*
+                }
+            }
+        }
+
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+        #vis struct #table<T> {
+            #(#(#docs)* #vis #idents: T,)*
+        }
+
+        impl<T> #table<T> {
+            #[allow(unused)]
+            #vis fn map<U, F: FnMut(&T) -> U>(&self, mut f: F) -> #table<U> {
+                #table {
+                    #(#idents: f(&self.#idents),)*
+                }
+            }
+
+            #[allow(unused)]
+            #vis fn for_each<F: FnMut(&T)>(&self, mut f: F) {
+                #(f(&self.#idents);)*
+            }
+
+            #[allow(unused)]
+            #vis fn for_each_enumerate<F: FnMut(&T, #layer)>(&self, mut f: F) {
+                #(f(&self.#idents, #layer::#variants);)*
+            }
+
+        }
+
+        impl<T> #table<Option<T>> {
+            #[allow(unused)]
+            #vis fn option_map<U, F: FnMut(&T) -> U>(&self, mut f: F) -> #table<Option<U>> {
+                self.map(|ot| ot.as_ref().map(|t| f(t)))
+            }
+
+            #[allow(unused)]
+            #vis fn option_and_then<U, F: FnMut(&T) -> Option<U>>(&self, mut f: F) -> #table<Option<U>> {
+                self.map(|ot| ot.as_ref().and_then(|t| f(t)))
+            }
+
+            #[allow(unused)]
+            #vis fn option_for_each<F: FnMut(&T)>(&self, mut f: F) {
+                #(if let Some(t) = self.#idents.as_ref() { f(t); })*
+            }
+
+            #[allow(unused)]
+            #vis fn option_for_each_enumerate<F: FnMut(&T, #layer)>(&self, mut f: F) {
+                #(if let Some(t) = self.#idents.as_ref() { f(t, #layer::#variants); })*
+            }
+        }
+    })
+}
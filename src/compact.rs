@@ -0,0 +1,449 @@
+//! An alternative serialization of [`SpatialTable`] which is much smaller than the default for
+//! large grids. Each layer is stored as a run-length encoded occupancy mask in row-major order,
+//! followed by the entities in the occupied cells. Use it on a field with
+//! `#[serde(with = "spatial_table::compact")]`.
+//!
+//! The encoding is versioned. Data written by an older version of this module can always be
+//! read by newer versions.
+
+use crate::{
+    validation_error, Coord, Entity, Footprint, Layers, Location, Migration, MigrationReport, Size,
+    SpatialTable,
+};
+use entity_table::ComponentTable;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Serialize, Deserialize)]
+struct CompactLayer<T> {
+    layer: T,
+    /// Lengths of alternating runs of unoccupied and occupied cells, starting with unoccupied
+    runs: Vec<u32>,
+    /// For stack layers, the number of entities in each occupied cell. Empty for other layers.
+    stack_heights: Vec<u32>,
+    /// The entities in each occupied cell in row-major order. Stacks are listed from bottom to
+    /// top.
+    entities: Vec<Entity>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CompactV1<T> {
+    size: Size,
+    layers: Vec<CompactLayer<T>>,
+    /// Entities with a coord but no layer
+    unlayered: Vec<(Entity, Coord)>,
+}
+
+/// Adds the footprints of entities which cover more than one cell. Such entities are stored in
+/// the layers at their anchor only.
+#[derive(Serialize, Deserialize)]
+struct CompactV2<T> {
+    size: Size,
+    layers: Vec<CompactLayer<T>>,
+    /// Entities with a coord but no layer
+    unlayered: Vec<(Entity, Coord)>,
+    footprints: Vec<(Entity, Footprint)>,
+}
+
+impl<T> From<CompactV1<T>> for CompactV2<T> {
+    fn from(
+        CompactV1 {
+            size,
+            layers,
+            unlayered,
+        }: CompactV1<T>,
+    ) -> Self {
+        Self {
+            size,
+            layers,
+            unlayered,
+            footprints: Vec::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+enum Compact<T> {
+    V1(CompactV1<T>),
+    V2(CompactV2<T>),
+}
+
+pub fn serialize<L, S>(spatial_table: &SpatialTable<L>, s: S) -> Result<S::Ok, S::Error>
+where
+    L: Layers,
+    L::Layer: Serialize,
+    S: Serializer,
+{
+    let layers = L::LAYERS
+        .iter()
+        .map(|&layer| {
+            let mut runs = Vec::new();
+            let mut run = 0;
+            let mut occupied = false;
+            let mut stack_heights = Vec::new();
+            let mut entities = Vec::new();
+            for (coord, cell) in spatial_table.enumerate() {
+                // entities covering multiple cells are only stored at their anchor
+                let anchored = |&entity: &Entity| spatial_table.coord_of(entity) == Some(coord);
+                let stack_height = entities.len();
+                if L::is_stack(layer) {
+                    entities.extend(spatial_table.stack_at(coord, layer).filter(anchored));
+                } else {
+                    entities.extend(cell.select_field(layer).filter(anchored));
+                }
+                let occupied_here = entities.len() > stack_height;
+                if occupied_here != occupied {
+                    runs.push(run);
+                    run = 0;
+                    occupied = occupied_here;
+                }
+                run += 1;
+                if L::is_stack(layer) && occupied {
+                    stack_heights.push((entities.len() - stack_height) as u32);
+                }
+            }
+            if occupied {
+                runs.push(run);
+            }
+            CompactLayer {
+                layer,
+                runs,
+                stack_heights,
+                entities,
+            }
+        })
+        .collect();
+    let unlayered = spatial_table
+        .location_component
+        .iter()
+        .filter(|(_, location)| location.layer.is_none())
+        .map(|(entity, location)| (entity, location.coord))
+        .collect();
+    let footprints = spatial_table
+        .footprints
+        .iter()
+        .map(|(entity, footprint)| (entity, footprint.clone()))
+        .collect();
+    Compact::V2(CompactV2 {
+        size: spatial_table.grid_size(),
+        layers,
+        unlayered,
+        footprints,
+    })
+    .serialize(s)
+}
+
+pub fn deserialize<'a, L, D>(d: D) -> Result<SpatialTable<L>, D::Error>
+where
+    L: Layers,
+    L::Layer: Deserialize<'a>,
+    D: Deserializer<'a>,
+{
+    let compact = Compact::<L::Layer>::deserialize(d)?.into_latest();
+    decode(compact, |compact_layer| {
+        (Some(compact_layer.layer), L::is_stack(compact_layer.layer))
+    })
+}
+
+/// A table loaded by [`deserialize_with_migration`], along with every change made to it
+type Migrated<L> = (SpatialTable<L>, MigrationReport<<L as Layers>::Layer>);
+
+/// Like [`SpatialTable::deserialize_with_migration`] for data in the compact format. `migrate`
+/// is called once with the name of each layer which isn't recognised.
+pub fn deserialize_with_migration<'a, L, D, F>(
+    d: D,
+    mut migrate: F,
+) -> Result<Migrated<L>, D::Error>
+where
+    L: Layers,
+    D: Deserializer<'a>,
+    F: FnMut(&str) -> Option<L::Layer>,
+{
+    let compact = Compact::<String>::deserialize(d)?.into_latest();
+    let mut migrations = Vec::new();
+    let spatial_table = decode(compact, |layer: &CompactLayer<String>| {
+        let name = &layer.layer;
+        if let Some(layer) = L::layer_from_name(name) {
+            return (Some(layer), L::is_stack(layer));
+        }
+        // the layer is decoded according to how it was saved, rather than the layer it's
+        // migrated to
+        let stack = !layer.stack_heights.is_empty();
+        match migrate(name) {
+            Some(to) => {
+                migrations.extend(layer.entities.iter().map(|&entity| Migration::Mapped {
+                    entity,
+                    from: name.clone(),
+                    to,
+                }));
+                (Some(to), stack)
+            }
+            None => {
+                migrations.extend(layer.entities.iter().map(|&entity| Migration::Dropped {
+                    entity,
+                    from: name.clone(),
+                }));
+                (None, stack)
+            }
+        }
+    })?;
+    Ok((spatial_table, migrations))
+}
+
+impl<T> Compact<T> {
+    fn into_latest(self) -> CompactV2<T> {
+        // when a new version is added, older versions are converted to it here
+        match self {
+            Self::V1(compact) => compact.into(),
+            Self::V2(compact) => compact,
+        }
+    }
+}
+
+/// Builds a spatial table from compact data. `resolve` returns the layer of the table which
+/// each layer in the data is loaded into, or `None` to leave out its entities, along with
+/// whether the layer was saved as a stack layer.
+fn decode<L, T, E, F>(
+    CompactV2 {
+        size,
+        layers,
+        unlayered,
+        footprints,
+    }: CompactV2<T>,
+    mut resolve: F,
+) -> Result<SpatialTable<L>, E>
+where
+    L: Layers,
+    E: de::Error,
+    F: FnMut(&CompactLayer<T>) -> (Option<L::Layer>, bool),
+{
+    let num_cells = size.count();
+    let mut location_component = ComponentTable::default();
+    let mut stack_order = Vec::new();
+    for compact_layer in layers {
+        let (layer, stack) = resolve(&compact_layer);
+        let layer = match layer {
+            Some(layer) => layer,
+            None => continue,
+        };
+        let CompactLayer {
+            runs,
+            stack_heights,
+            entities,
+            ..
+        } = compact_layer;
+        let mut entities = entities.into_iter();
+        let mut stack_heights = stack_heights.into_iter();
+        let mut index = 0;
+        for (i, &run) in runs.iter().enumerate() {
+            let run = run as usize;
+            if index + run > num_cells {
+                return Err(de::Error::custom("run extends beyond the end of the grid"));
+            }
+            if i % 2 == 1 {
+                for index in index..(index + run) {
+                    let coord = Coord::new(
+                        (index % size.width() as usize) as i32,
+                        (index / size.width() as usize) as i32,
+                    );
+                    let height = if stack {
+                        stack_heights
+                            .next()
+                            .ok_or_else(|| de::Error::custom("missing stack height"))?
+                    } else {
+                        1
+                    };
+                    for _ in 0..height {
+                        let entity = entities
+                            .next()
+                            .ok_or_else(|| de::Error::custom("missing entity"))?;
+                        if location_component
+                            .insert(entity, (coord, layer).into())
+                            .is_some()
+                        {
+                            return Err(de::Error::custom("entity appears more than once"));
+                        }
+                        if L::is_stack(layer) {
+                            stack_order.push(entity);
+                        }
+                    }
+                }
+            }
+            index += run;
+        }
+        if entities.next().is_some() || stack_heights.next().is_some() {
+            return Err(de::Error::custom("unexpected trailing data for layer"));
+        }
+    }
+    for (entity, coord) in unlayered {
+        if location_component
+            .insert(entity, Location { coord, layer: None })
+            .is_some()
+        {
+            return Err(de::Error::custom("entity appears more than once"));
+        }
+    }
+    SpatialTable::from_location_component(size, location_component, &stack_order, footprints)
+        .map_err(validation_error)
+}
+
+#[cfg(test)]
+mod test {
+    crate::declare_layers_module! {
+        layers {
+            feature: Feature,
+            item: Item [stack],
+            character: Character,
+        }
+    }
+    use crate::{Coord, Size};
+    use entity_table::EntityAllocator;
+    use layers::{Layer, Layers};
+    use serde::{Deserialize, Serialize};
+    type SpatialTable = crate::SpatialTable<Layers>;
+
+    #[derive(Serialize, Deserialize)]
+    struct Compact(#[serde(with = "super")] SpatialTable);
+
+    fn assert_same(a: &SpatialTable, b: &SpatialTable) {
+        assert_eq!(a.grid_size(), b.grid_size());
+        assert!(a.snapshot().diff(&b.snapshot()).is_empty());
+        for (coord, cell) in a.enumerate() {
+            assert_eq!(cell, b.layers_at_checked(coord));
+            assert!(a
+                .stack_at(coord, Layer::Item)
+                .eq(b.stack_at(coord, Layer::Item)));
+        }
+        assert_eq!(b.check_invariants(), vec![]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(40, 30));
+        for y in 0..30 {
+            for x in 0..40 {
+                if x == 0 || y == 0 || x == 39 || y == 29 {
+                    let wall = entity_allocator.alloc();
+                    spatial_table
+                        .update(wall, (Coord::new(x, y), Layer::Feature).into())
+                        .unwrap();
+                }
+            }
+        }
+        for i in 0..3 {
+            let item = entity_allocator.alloc();
+            spatial_table
+                .update(item, (Coord::new(5, 5 + i % 2), Layer::Item).into())
+                .unwrap();
+        }
+        let hero = entity_allocator.alloc();
+        spatial_table
+            .update(hero, (Coord::new(39, 29), Layer::Character).into())
+            .unwrap();
+        let ghost = entity_allocator.alloc();
+        spatial_table
+            .update_coord(ghost, Coord::new(-4, 100))
+            .unwrap();
+
+        let compact = serde_json::to_string(&Compact(spatial_table)).unwrap();
+        let Compact(from_compact) = serde_json::from_str(&compact).unwrap();
+        let Compact(spatial_table) = serde_json::from_str(&compact).unwrap();
+        let default = serde_json::to_string(&spatial_table).unwrap();
+        let from_default: SpatialTable = serde_json::from_str(&default).unwrap();
+        assert_same(&from_default, &from_compact);
+        assert_eq!(
+            serde_json::to_string(&Compact(from_default)).unwrap(),
+            compact
+        );
+        assert!(compact.len() < default.len() / 2);
+
+        let empty = serde_json::to_string(&Compact(SpatialTable::new(Size::new(3, 3)))).unwrap();
+        let Compact(from_empty) = serde_json::from_str(&empty).unwrap();
+        assert_same(&SpatialTable::new(Size::new(3, 3)), &from_empty);
+    }
+
+    #[test]
+    fn invalid_data() {
+        let truncated = r#"{"V1":{"size":{"x":2,"y":2},"layers":[{"layer":"Feature","runs":[3,2],"stack_heights":[],"entities":[]}],"unlayered":[]}}"#;
+        assert!(serde_json::from_str::<Compact>(truncated).is_err());
+        let missing_entity = r#"{"V1":{"size":{"x":2,"y":2},"layers":[{"layer":"Feature","runs":[0,1],"stack_heights":[],"entities":[]}],"unlayered":[]}}"#;
+        assert!(serde_json::from_str::<Compact>(missing_entity).is_err());
+        let is_duplicate = |data: &str| {
+            serde_json::from_str::<Compact>(data)
+                .err()
+                .is_some_and(|error| error.to_string().contains("more than once"))
+        };
+        let duplicate_in_layer = r#"{"V1":{"size":{"x":2,"y":2},"layers":[{"layer":"Feature","runs":[0,2],"stack_heights":[],"entities":[{"id":0,"index":0},{"id":0,"index":0}]}],"unlayered":[]}}"#;
+        assert!(is_duplicate(duplicate_in_layer));
+        let duplicate_across_layers = r#"{"V1":{"size":{"x":2,"y":2},"layers":[{"layer":"Feature","runs":[0,1],"stack_heights":[],"entities":[{"id":0,"index":0}]},{"layer":"Character","runs":[1,1],"stack_heights":[],"entities":[{"id":0,"index":0}]}],"unlayered":[]}}"#;
+        assert!(is_duplicate(duplicate_across_layers));
+        let duplicate_unlayered = r#"{"V1":{"size":{"x":2,"y":2},"layers":[{"layer":"Feature","runs":[0,1],"stack_heights":[],"entities":[{"id":0,"index":0}]}],"unlayered":[[{"id":0,"index":0},{"x":5,"y":5}]]}}"#;
+        assert!(is_duplicate(duplicate_unlayered));
+        let missing_stack_height = r#"{"V1":{"size":{"x":2,"y":2},"layers":[{"layer":"Item","runs":[0,2],"stack_heights":[1],"entities":[{"id":0,"index":0},{"id":1,"index":1}]}],"unlayered":[]}}"#;
+        assert!(serde_json::from_str::<Compact>(missing_stack_height).is_err());
+        let extra_stack_height = r#"{"V1":{"size":{"x":2,"y":2},"layers":[{"layer":"Item","runs":[0,1],"stack_heights":[1,1],"entities":[{"id":0,"index":0}]}],"unlayered":[]}}"#;
+        assert!(serde_json::from_str::<Compact>(extra_stack_height).is_err());
+    }
+
+    #[test]
+    fn migration() {
+        mod v2 {
+            crate::declare_layers_module! {
+                layers {
+                    floor: Floor,
+                    character: Character,
+                }
+            }
+            pub use layers::{Layer, Layers};
+        }
+        use crate::Migration;
+
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(4, 4));
+        let wall = entity_allocator.alloc();
+        let item = entity_allocator.alloc();
+        let hero = entity_allocator.alloc();
+        spatial_table
+            .update(wall, (Coord::new(1, 1), Layer::Feature).into())
+            .unwrap();
+        spatial_table
+            .update(item, (Coord::new(2, 2), Layer::Item).into())
+            .unwrap();
+        spatial_table
+            .update(hero, (Coord::new(1, 1), Layer::Character).into())
+            .unwrap();
+        let saved = serde_json::to_string(&Compact(spatial_table)).unwrap();
+        let mut migrated = Vec::new();
+        let (spatial_table, migrations) = super::deserialize_with_migration::<v2::Layers, _, _>(
+            &mut serde_json::Deserializer::from_str(&saved),
+            |name| {
+                migrated.push(name.to_string());
+                (name == "Feature").then_some(v2::Layer::Floor)
+            },
+        )
+        .unwrap();
+        assert_eq!(migrated, vec!["Feature", "Item"]);
+        assert_eq!(
+            migrations,
+            vec![
+                Migration::Mapped {
+                    entity: wall,
+                    from: "Feature".to_string(),
+                    to: v2::Layer::Floor,
+                },
+                Migration::Dropped {
+                    entity: item,
+                    from: "Item".to_string(),
+                },
+            ],
+        );
+        assert_eq!(
+            *spatial_table.layers_at_checked(Coord::new(1, 1)),
+            v2::Layers {
+                floor: Some(wall),
+                character: Some(hero),
+            },
+        );
+        assert_eq!(spatial_table.location_of(item), None);
+    }
+}
@@ -0,0 +1,505 @@
+use crate::{Coord, Entity, Layers, Size, SpatialTable, Storage, UpdateError};
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The cells covered by an entity, as offsets from the coord of its location (its anchor). An
+/// entity on a layer occupies that layer in every covered cell, so every covered cell returns
+/// the entity from [`SpatialTable::layers_at`]. The anchor itself is always covered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Footprint {
+    /// Sorted in row-major order without duplicates, and always containing the origin
+    offsets: Vec<Coord>,
+}
+
+impl Default for Footprint {
+    fn default() -> Self {
+        Self::single()
+    }
+}
+
+impl Footprint {
+    /// Covers only the anchor. This is the footprint of every entity unless otherwise
+    /// specified.
+    pub fn single() -> Self {
+        Self {
+            offsets: vec![Coord::new(0, 0)],
+        }
+    }
+    /// Covers a rectangle whose top-left corner is the anchor
+    pub fn rect(size: Size) -> Self {
+        Self::from_offsets(size.coord_iter_row_major())
+    }
+    /// Covers the anchor and each of `offsets`, which needn't be contiguous
+    pub fn from_offsets<I: IntoIterator<Item = Coord>>(offsets: I) -> Self {
+        let mut offsets = offsets
+            .into_iter()
+            .chain(Some(Coord::new(0, 0)))
+            .collect::<Vec<_>>();
+        offsets.sort_unstable_by_key(|offset| (offset.y, offset.x));
+        offsets.dedup();
+        Self { offsets }
+    }
+    pub fn offsets(&self) -> &[Coord] {
+        &self.offsets
+    }
+    pub fn is_single(&self) -> bool {
+        self.offsets.len() == 1
+    }
+    /// The coords covered by the footprint when its anchor is at `anchor`
+    pub fn coords(&self, anchor: Coord) -> impl Iterator<Item = Coord> + '_ {
+        self.offsets.iter().map(move |&offset| anchor + offset)
+    }
+}
+
+/// Serialized as its list of offsets
+#[cfg(feature = "serialize")]
+impl Serialize for Footprint {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        self.offsets.serialize(s)
+    }
+}
+
+#[cfg(feature = "serialize")]
+impl<'a> Deserialize<'a> for Footprint {
+    fn deserialize<D: Deserializer<'a>>(d: D) -> Result<Self, D::Error> {
+        Vec::<Coord>::deserialize(d).map(Self::from_offsets)
+    }
+}
+
+impl<L: Layers, S: Storage<L>> SpatialTable<L, S> {
+    /// Changes the cells covered by an entity. If the entity is on a layer, it's moved into
+    /// every cell of the new footprint, and if any of these cells is out of bounds or occupied
+    /// the table is left unchanged and an error is returned. A footprint may be set before the
+    /// entity is added to the table, and is discarded when the entity is removed. Like changes
+    /// to locations, changing a footprint is reported to observers and the journal, and
+    /// recorded in the history.
+    pub fn set_footprint(
+        &mut self,
+        entity: Entity,
+        footprint: Footprint,
+    ) -> Result<(), UpdateError> {
+        let unchanged = match self.footprints.get(entity) {
+            Some(original_footprint) => *original_footprint == footprint,
+            None => footprint.is_single(),
+        };
+        if unchanged {
+            return Ok(());
+        }
+        let location = self.location_of(entity).copied();
+        let errors = self.place_all(&[(entity, location)], true, &[(entity, footprint)]);
+        match errors.first() {
+            Some(&(_, error)) => Err(error),
+            None => Ok(()),
+        }
+    }
+    /// Resets the footprint of an entity to cover only its anchor. Unlike
+    /// [`Self::set_footprint`] this can't fail, since the anchor is covered by every footprint
+    /// and so is already occupied by the entity.
+    pub fn clear_footprint(&mut self, entity: Entity) {
+        let result = self.set_footprint(entity, Footprint::single());
+        debug_assert!(result.is_ok(), "Failed to clear footprint");
+    }
+    /// The footprint of an entity, or `None` if it covers only its anchor
+    pub fn footprint_of(&self, entity: Entity) -> Option<&Footprint> {
+        self.footprints.get(entity)
+    }
+    /// The coords covered by an entity when its anchor is at `anchor`
+    pub(crate) fn covered_coords(&self, entity: Entity, anchor: Coord) -> Vec<Coord> {
+        match self.footprints.get(entity) {
+            Some(footprint) => footprint.coords(anchor).collect(),
+            None => vec![anchor],
+        }
+    }
+    /// Returns `true` iff the entity covers `coord` when its anchor is at `anchor`
+    pub(crate) fn covers(&self, entity: Entity, anchor: Coord, coord: Coord) -> bool {
+        match self.footprints.get(entity) {
+            Some(footprint) => footprint
+                .offsets
+                .binary_search_by_key(&((coord - anchor).y, (coord - anchor).x), |offset| {
+                    (offset.y, offset.x)
+                })
+                .is_ok(),
+            None => anchor == coord,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    crate::declare_layers_module! {
+        layers {
+            feature: Feature,
+            item: Item [stack],
+            character: Character,
+        }
+    }
+    use super::Footprint;
+    use crate::{
+        Coord, Displace, Displacement, Size, SwapError, SwapLayersError, UpdateError,
+        UpdateLayerError,
+    };
+    use entity_table::EntityAllocator;
+    use layers::{Layer, Layers};
+    type SpatialTable = crate::SpatialTable<Layers>;
+
+    #[test]
+    fn footprints() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        spatial_table.enable_occupancy_bitmaps();
+        let boss = entity_allocator.alloc();
+        let hero = entity_allocator.alloc();
+        let wall = entity_allocator.alloc();
+        spatial_table
+            .update(hero, (Coord::new(5, 5), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(wall, (Coord::new(0, 3), Layer::Feature).into())
+            .unwrap();
+        spatial_table
+            .set_footprint(boss, Footprint::rect(Size::new(2, 2)))
+            .unwrap();
+        spatial_table
+            .update(boss, (Coord::new(1, 1), Layer::Character).into())
+            .unwrap();
+        for coord in [(1, 1), (2, 1), (1, 2), (2, 2)] {
+            let coord = Coord::new(coord.0, coord.1);
+            assert_eq!(spatial_table.entity_at(coord, Layer::Character), Some(boss));
+        }
+        assert_eq!(spatial_table.coord_of(boss), Some(Coord::new(1, 1)));
+        assert!(spatial_table.is_region_free(Coord::new(3, 0), Size::new(2, 5), Layer::Character));
+
+        // moving into a cell covered by the boss's current footprint
+        spatial_table.update_coord(boss, Coord::new(2, 1)).unwrap();
+        assert_eq!(
+            spatial_table.entity_at(Coord::new(1, 1), Layer::Character),
+            None
+        );
+        assert_eq!(
+            spatial_table.entity_at(Coord::new(3, 2), Layer::Character),
+            Some(boss)
+        );
+
+        // the footprint is checked as a whole
+        assert_eq!(
+            spatial_table.update_coord(boss, Coord::new(4, 4)),
+            Err(UpdateError::OccupiedBy(hero)),
+        );
+        assert_eq!(
+            spatial_table.update_coord(boss, Coord::new(9, 0)),
+            Err(UpdateError::DestinationOutOfBounds),
+        );
+        assert_eq!(spatial_table.coord_of(boss), Some(Coord::new(2, 1)));
+        assert_eq!(
+            spatial_table
+                .entities_in_rect(Coord::new(0, 0), Size::new(10, 10), Layer::Character)
+                .map(|(coord, _, entity)| (coord, entity))
+                .collect::<Vec<_>>(),
+            vec![
+                (Coord::new(2, 1), boss),
+                (Coord::new(3, 1), boss),
+                (Coord::new(2, 2), boss),
+                (Coord::new(3, 2), boss),
+                (Coord::new(5, 5), hero),
+            ],
+        );
+        assert_eq!(spatial_table.check_invariants(), vec![]);
+
+        // an irregular footprint which would overlap the hero
+        let shape = Footprint::from_offsets([Coord::new(-1, 0), Coord::new(3, 4)]);
+        assert_eq!(shape.offsets().len(), 3);
+        assert_eq!(
+            spatial_table.set_footprint(boss, shape.clone()),
+            Err(UpdateError::OccupiedBy(hero)),
+        );
+        assert_eq!(
+            spatial_table.footprint_of(boss),
+            Some(&Footprint::rect(Size::new(2, 2)))
+        );
+        spatial_table.update_coord(hero, Coord::new(6, 6)).unwrap();
+        spatial_table.set_footprint(boss, shape).unwrap();
+        assert_eq!(
+            spatial_table.entity_at(Coord::new(1, 1), Layer::Character),
+            Some(boss)
+        );
+        assert_eq!(
+            spatial_table.entity_at(Coord::new(5, 5), Layer::Character),
+            Some(boss)
+        );
+        assert_eq!(
+            spatial_table.entity_at(Coord::new(3, 1), Layer::Character),
+            None
+        );
+        assert_eq!(spatial_table.check_invariants(), vec![]);
+
+        spatial_table.update_layer(boss, Layer::Feature).unwrap();
+        assert_eq!(
+            spatial_table.entity_at(Coord::new(5, 5), Layer::Feature),
+            Some(boss)
+        );
+        assert!(!spatial_table.is_occupied(Coord::new(5, 5), Layer::Character));
+        assert_eq!(
+            spatial_table.swap(boss, wall),
+            Err(SwapError::DestinationOutOfBounds)
+        );
+        spatial_table.update_coord(wall, Coord::new(1, 3)).unwrap();
+        spatial_table.swap(boss, wall).unwrap();
+        assert_eq!(
+            spatial_table.entity_at(Coord::new(4, 7), Layer::Feature),
+            Some(boss)
+        );
+        assert_eq!(
+            spatial_table.entity_at(Coord::new(2, 1), Layer::Feature),
+            Some(wall)
+        );
+        assert_eq!(
+            spatial_table.entity_at(Coord::new(5, 5), Layer::Feature),
+            None
+        );
+        assert_eq!(spatial_table.check_invariants(), vec![]);
+
+        spatial_table.clear_footprint(boss);
+        assert_eq!(spatial_table.footprint_of(boss), None);
+        assert!(!spatial_table.is_occupied(Coord::new(4, 7), Layer::Feature));
+        spatial_table
+            .set_footprint(boss, Footprint::rect(Size::new(1, 3)))
+            .unwrap();
+        assert!(spatial_table.is_occupied(Coord::new(1, 5), Layer::Feature));
+        spatial_table.remove(boss);
+        assert!(!spatial_table.is_region_free(Coord::new(0, 0), Size::new(10, 10), Layer::Feature));
+        assert!(spatial_table.is_region_free(Coord::new(1, 3), Size::new(1, 3), Layer::Feature));
+        assert_eq!(spatial_table.footprint_of(boss), None);
+        assert_eq!(spatial_table.check_invariants(), vec![]);
+    }
+
+    #[test]
+    fn undo_removal() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let boss = entity_allocator.alloc();
+        let hero = entity_allocator.alloc();
+        let shape = Footprint::rect(Size::new(2, 2));
+        spatial_table.enable_history();
+        spatial_table.set_footprint(boss, shape.clone()).unwrap();
+        spatial_table
+            .update(boss, (Coord::new(1, 1), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(hero, (Coord::new(5, 5), Layer::Character).into())
+            .unwrap();
+        spatial_table.end_step();
+
+        spatial_table.remove(boss);
+        assert_eq!(spatial_table.footprint_of(boss), None);
+        spatial_table.undo().unwrap();
+        assert_eq!(spatial_table.footprint_of(boss), Some(&shape));
+        assert_eq!(
+            spatial_table.entity_at(Coord::new(2, 2), Layer::Character),
+            Some(boss)
+        );
+        spatial_table.redo().unwrap();
+        assert_eq!(spatial_table.footprint_of(boss), None);
+        assert!(!spatial_table.is_occupied(Coord::new(2, 2), Layer::Character));
+        spatial_table.undo().unwrap();
+        assert_eq!(spatial_table.footprint_of(boss), Some(&shape));
+
+        spatial_table.clear();
+        assert_eq!(spatial_table.footprint_of(boss), None);
+        spatial_table.undo().unwrap();
+        assert_eq!(
+            spatial_table.entity_at(Coord::new(2, 2), Layer::Character),
+            Some(boss)
+        );
+
+        // undoing the placement of the boss removes it, and redoing it restores the footprint
+        spatial_table.undo().unwrap();
+        assert_eq!(spatial_table.coord_of(boss), None);
+        assert_eq!(spatial_table.footprint_of(boss), None);
+        spatial_table.redo().unwrap();
+        assert_eq!(
+            spatial_table.entity_at(Coord::new(2, 2), Layer::Character),
+            Some(boss)
+        );
+        assert_eq!(spatial_table.check_invariants(), vec![]);
+    }
+
+    #[test]
+    fn undo_footprint_changes() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let boss = entity_allocator.alloc();
+        let minion = entity_allocator.alloc();
+        let shape = Footprint::rect(Size::new(2, 2));
+        spatial_table.enable_history();
+        spatial_table
+            .update(boss, (Coord::new(1, 1), Layer::Character).into())
+            .unwrap();
+        spatial_table.begin_step("grow");
+        spatial_table.set_footprint(boss, shape.clone()).unwrap();
+        spatial_table.set_footprint(minion, shape.clone()).unwrap();
+        spatial_table.end_step();
+
+        assert_eq!(spatial_table.undo(), Ok(Some("grow".to_string())));
+        assert_eq!(spatial_table.footprint_of(boss), None);
+        assert_eq!(spatial_table.footprint_of(minion), None);
+        assert!(!spatial_table.is_occupied(Coord::new(2, 2), Layer::Character));
+        assert_eq!(spatial_table.redo(), Ok(Some("grow".to_string())));
+        assert_eq!(spatial_table.footprint_of(boss), Some(&shape));
+        assert_eq!(spatial_table.footprint_of(minion), Some(&shape));
+        assert_eq!(
+            spatial_table.entity_at(Coord::new(2, 2), Layer::Character),
+            Some(boss)
+        );
+
+        // a footprint changed in a transaction is restored along with the location
+        let mut transaction = spatial_table.transaction();
+        transaction.update_coord(boss, Coord::new(5, 5));
+        transaction.set_footprint(boss, Footprint::rect(Size::new(3, 1)));
+        transaction.commit().unwrap();
+        spatial_table.undo().unwrap();
+        assert_eq!(spatial_table.coord_of(boss), Some(Coord::new(1, 1)));
+        assert_eq!(spatial_table.footprint_of(boss), Some(&shape));
+        assert!(!spatial_table.is_occupied(Coord::new(7, 5), Layer::Character));
+        assert_eq!(spatial_table.check_invariants(), vec![]);
+    }
+
+    #[test]
+    fn clearing_footprint_cannot_fail() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let boss = entity_allocator.alloc();
+        let coin = entity_allocator.alloc();
+        let ghost = entity_allocator.alloc();
+        spatial_table
+            .update(coin, (Coord::new(1, 1), Layer::Item).into())
+            .unwrap();
+        spatial_table
+            .set_footprint(boss, Footprint::rect(Size::new(2, 2)))
+            .unwrap();
+        spatial_table
+            .update(boss, (Coord::new(0, 0), Layer::Item).into())
+            .unwrap();
+        spatial_table
+            .set_footprint(ghost, Footprint::rect(Size::new(3, 3)))
+            .unwrap();
+        spatial_table.update_coord(ghost, Coord::new(9, 9)).unwrap();
+
+        // the anchor is still occupied by the entity, even when it shares a stack
+        spatial_table.clear_footprint(boss);
+        assert_eq!(
+            spatial_table
+                .stack_at(Coord::new(1, 1), Layer::Item)
+                .collect::<Vec<_>>(),
+            vec![coin]
+        );
+        assert_eq!(
+            spatial_table.entity_at(Coord::new(0, 0), Layer::Item),
+            Some(boss)
+        );
+        // an entity with no layer isn't placed in any cell
+        spatial_table.clear_footprint(ghost);
+        assert_eq!(spatial_table.footprint_of(ghost), None);
+        assert_eq!(spatial_table.check_invariants(), vec![]);
+    }
+
+    #[test]
+    fn displacing_footprints() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let boss = entity_allocator.alloc();
+        let hero = entity_allocator.alloc();
+        let villain = entity_allocator.alloc();
+        spatial_table
+            .set_footprint(boss, Footprint::rect(Size::new(2, 2)))
+            .unwrap();
+        spatial_table
+            .update(boss, (Coord::new(1, 1), Layer::Character).into())
+            .unwrap();
+
+        // the boss doesn't displace itself
+        for displace in [Displace::Fail, Displace::Swap] {
+            assert_eq!(
+                spatial_table.update_coord_displacing(boss, Coord::new(2, 1), displace),
+                Ok(Displacement::None),
+            );
+            spatial_table.update_coord(boss, Coord::new(1, 1)).unwrap();
+        }
+
+        // an occupant of any covered cell is displaced
+        spatial_table
+            .update(hero, (Coord::new(4, 2), Layer::Character).into())
+            .unwrap();
+        assert_eq!(
+            spatial_table.update_coord_displacing(boss, Coord::new(3, 1), Displace::Fail),
+            Err(UpdateError::OccupiedBy(hero)),
+        );
+        assert_eq!(
+            spatial_table.update_coord_displacing(
+                boss,
+                Coord::new(3, 1),
+                Displace::Push(Coord::new(2, 0))
+            ),
+            Ok(Displacement::Pushed {
+                entity: hero,
+                coord: Coord::new(6, 2),
+            }),
+        );
+        assert_eq!(
+            spatial_table.update_coord_displacing(boss, Coord::new(5, 2), Displace::Swap),
+            Ok(Displacement::Swapped(hero)),
+        );
+        assert_eq!(spatial_table.coord_of(hero), Some(Coord::new(3, 1)));
+        assert_eq!(
+            spatial_table.entity_at(Coord::new(6, 3), Layer::Character),
+            Some(boss)
+        );
+
+        // only one occupant can be displaced
+        spatial_table
+            .update(villain, (Coord::new(4, 1), Layer::Character).into())
+            .unwrap();
+        assert_eq!(
+            spatial_table.update_coord_displacing(boss, Coord::new(3, 0), Displace::Evict),
+            Err(UpdateError::OccupiedBy(villain)),
+        );
+        assert_eq!(
+            spatial_table.update_coord_displacing(boss, Coord::new(9, 0), Displace::Evict),
+            Err(UpdateError::DestinationOutOfBounds),
+        );
+        assert_eq!(spatial_table.coord_of(boss), Some(Coord::new(5, 2)));
+        assert_eq!(spatial_table.layer_of(hero), Some(Layer::Character));
+        assert_eq!(spatial_table.check_invariants(), vec![]);
+    }
+
+    #[test]
+    fn footprint_out_of_bounds_without_layer() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let boss = entity_allocator.alloc();
+        spatial_table
+            .set_footprint(boss, Footprint::rect(Size::new(3, 3)))
+            .unwrap();
+        spatial_table.update_coord(boss, Coord::new(9, 9)).unwrap();
+        assert_eq!(
+            spatial_table.update_layer(boss, Layer::Character),
+            Err(UpdateLayerError::DestinationOutOfBounds),
+        );
+        let wall = entity_allocator.alloc();
+        spatial_table
+            .update(wall, (Coord::new(0, 0), Layer::Feature).into())
+            .unwrap();
+        assert_eq!(
+            spatial_table.swap_layers(boss, wall),
+            Err(SwapLayersError::DestinationOutOfBounds),
+        );
+        assert_eq!(spatial_table.layer_of(boss), None);
+        assert_eq!(spatial_table.layer_of(wall), Some(Layer::Feature));
+        spatial_table.update_coord(boss, Coord::new(7, 7)).unwrap();
+        spatial_table.update_layer(boss, Layer::Character).unwrap();
+        assert_eq!(
+            spatial_table.entity_at(Coord::new(9, 9), Layer::Character),
+            Some(boss)
+        );
+        assert_eq!(spatial_table.check_invariants(), vec![]);
+    }
+}
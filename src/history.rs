@@ -0,0 +1,334 @@
+use crate::{Entity, Footprint, Layers, Location, SpatialTable, Storage, TransactionConflict};
+use std::collections::HashMap;
+
+/// A change to the location of a single entity
+type Change<L> = (Entity, Option<Location<L>>, Option<Location<L>>);
+
+/// A change to the footprint of a single entity, where `None` covers only the anchor
+type FootprintChange = (Entity, Option<Footprint>, Option<Footprint>);
+
+#[derive(Debug)]
+struct Step<L> {
+    name: String,
+    changes: Vec<Change<L>>,
+    footprint_changes: Vec<FootprintChange>,
+}
+
+impl<L> Step<L> {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            changes: Vec::new(),
+            footprint_changes: Vec::new(),
+        }
+    }
+    fn is_empty(&self) -> bool {
+        self.changes.is_empty() && self.footprint_changes.is_empty()
+    }
+}
+
+/// Records changes to a [`SpatialTable`] as a sequence of steps which can be undone and redone
+#[derive(Debug)]
+pub(crate) struct History<L> {
+    undo: Vec<Step<L>>,
+    redo: Vec<Step<L>>,
+    current: Option<Step<L>>,
+}
+
+impl<L> Default for History<L> {
+    fn default() -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            current: None,
+        }
+    }
+}
+
+impl<L> History<L> {
+    pub(crate) fn record(&mut self, change: Change<L>) {
+        self.current_step().changes.push(change);
+    }
+    pub(crate) fn record_footprint(&mut self, change: FootprintChange) {
+        self.current_step().footprint_changes.push(change);
+    }
+    fn current_step(&mut self) -> &mut Step<L> {
+        self.redo.clear();
+        self.current.get_or_insert_with(|| Step::new(String::new()))
+    }
+    fn end_step(&mut self) {
+        if let Some(step) = self.current.take() {
+            if !step.is_empty() {
+                self.undo.push(step);
+            }
+        }
+    }
+}
+
+impl<L: Layers, S: Storage<L>> SpatialTable<L, S> {
+    /// Starts recording changes to the table so they can be undone. Has no effect if history is
+    /// already enabled.
+    pub fn enable_history(&mut self) {
+        if self.history.is_none() {
+            self.history = Some(History::default());
+        }
+    }
+    /// Stops recording changes, discarding all undo and redo steps
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+    /// Starts a new step with the given name. All changes made until the next call to
+    /// [`Self::begin_step`] or [`Self::end_step`] are undone and redone together. Changes made
+    /// outside of an explicit step are grouped into an unnamed step, which ends at the next step
+    /// boundary or undo.
+    pub fn begin_step<N: Into<String>>(&mut self, name: N) {
+        if let Some(history) = self.history.as_mut() {
+            history.end_step();
+            history.current = Some(Step::new(name.into()));
+        }
+    }
+    /// Ends the current step. Steps containing no changes are discarded.
+    pub fn end_step(&mut self) {
+        if let Some(history) = self.history.as_mut() {
+            history.end_step();
+        }
+    }
+    pub fn can_undo(&self) -> bool {
+        self.history.as_ref().is_some_and(|history| {
+            !history.undo.is_empty()
+                || history
+                    .current
+                    .as_ref()
+                    .is_some_and(|step| !step.is_empty())
+        })
+    }
+    pub fn can_redo(&self) -> bool {
+        self.history
+            .as_ref()
+            .is_some_and(|history| !history.redo.is_empty())
+    }
+    /// Ends the current step, then restores the location and footprint of every entity
+    /// changed by the most recent step to those before the step. Returns the name of the step
+    /// that was undone, or `None` if there was nothing to undo. If the table was changed while
+    /// history was disabled such that the step can't be undone, the table is left unchanged and
+    /// the conflicts are returned.
+    pub fn undo(&mut self) -> Result<Option<String>, Vec<TransactionConflict>> {
+        let mut history = match self.history.take() {
+            Some(history) => history,
+            None => return Ok(None),
+        };
+        history.end_step();
+        let result = match history.undo.pop() {
+            None => Ok(None),
+            Some(step) => {
+                let mut moves = Vec::new();
+                let mut move_index_by_entity = HashMap::new();
+                for &(entity, original_location, _) in step.changes.iter() {
+                    move_index_by_entity.entry(entity).or_insert_with(|| {
+                        moves.push((entity, original_location));
+                        moves.len() - 1
+                    });
+                }
+                let mut footprints = Vec::new();
+                let mut footprint_index_by_entity = HashMap::new();
+                for (entity, original_footprint, _) in step.footprint_changes.iter() {
+                    footprint_index_by_entity.entry(*entity).or_insert_with(|| {
+                        footprints.push((*entity, original_footprint.clone()));
+                        footprints.len() - 1
+                    });
+                }
+                self.apply_step(
+                    step,
+                    moves,
+                    footprints,
+                    &mut history.undo,
+                    &mut history.redo,
+                )
+            }
+        };
+        self.history = Some(history);
+        result
+    }
+    /// Reapplies the most recently undone step. Returns the name of the step that was redone,
+    /// or `None` if there was nothing to redo.
+    pub fn redo(&mut self) -> Result<Option<String>, Vec<TransactionConflict>> {
+        let mut history = match self.history.take() {
+            Some(history) => history,
+            None => return Ok(None),
+        };
+        let result = match history.redo.pop() {
+            None => Ok(None),
+            Some(step) => {
+                let mut moves: Vec<(Entity, Option<Location<L::Layer>>)> = Vec::new();
+                let mut move_index_by_entity = HashMap::new();
+                for &(entity, _, location) in step.changes.iter() {
+                    let index = *move_index_by_entity.entry(entity).or_insert_with(|| {
+                        moves.push((entity, location));
+                        moves.len() - 1
+                    });
+                    moves[index].1 = location;
+                }
+                let mut footprints: Vec<(Entity, Option<Footprint>)> = Vec::new();
+                let mut footprint_index_by_entity = HashMap::new();
+                for (entity, _, footprint) in step.footprint_changes.iter() {
+                    let index = *footprint_index_by_entity.entry(*entity).or_insert_with(|| {
+                        footprints.push((*entity, None));
+                        footprints.len() - 1
+                    });
+                    footprints[index].1 = footprint.clone();
+                }
+                self.apply_step(
+                    step,
+                    moves,
+                    footprints,
+                    &mut history.redo,
+                    &mut history.undo,
+                )
+            }
+        };
+        self.history = Some(history);
+        result
+    }
+    /// Moves entities to the locations in `moves` and gives them the footprints in
+    /// `footprints`, as a single transaction. On success `step` is pushed onto `to`, otherwise
+    /// it's returned to `from`.
+    fn apply_step(
+        &mut self,
+        step: Step<L::Layer>,
+        mut moves: Vec<(Entity, Option<Location<L::Layer>>)>,
+        footprints: Vec<(Entity, Option<Footprint>)>,
+        from: &mut Vec<Step<L::Layer>>,
+        to: &mut Vec<Step<L::Layer>>,
+    ) -> Result<Option<String>, Vec<TransactionConflict>> {
+        let footprints = footprints
+            .into_iter()
+            .filter(|(entity, footprint)| self.footprints.get(*entity) != footprint.as_ref())
+            .map(|(entity, footprint)| (entity, footprint.unwrap_or_else(Footprint::single)))
+            .collect::<Vec<_>>();
+        // entities whose footprint changes must be moved even if their location doesn't
+        for &(entity, _) in footprints.iter() {
+            if !moves.iter().any(|&(other, _)| other == entity) {
+                moves.push((entity, self.location_of(entity).copied()));
+            }
+        }
+        let errors = self.place_all(&moves, true, &footprints);
+        if errors.is_empty() {
+            let name = step.name.clone();
+            to.push(step);
+            Ok(Some(name))
+        } else {
+            from.push(step);
+            Err(errors
+                .into_iter()
+                .map(|(entity, error)| TransactionConflict::new(entity, error))
+                .collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    crate::declare_layers_module! {
+        layers {
+            feature: Feature,
+            character: Character,
+        }
+    }
+    use crate::{Coord, Size};
+    use entity_table::EntityAllocator;
+    use layers::{Layer, Layers};
+    type SpatialTable = crate::SpatialTable<Layers>;
+
+    #[test]
+    fn undo_redo() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let wall = entity_allocator.alloc();
+        let hero = entity_allocator.alloc();
+        spatial_table.enable_history();
+        spatial_table
+            .update(wall, (Coord::new(0, 0), Layer::Feature).into())
+            .unwrap();
+        spatial_table.begin_step("place hero");
+        spatial_table
+            .update(hero, (Coord::new(1, 1), Layer::Character).into())
+            .unwrap();
+        spatial_table.update_coord(hero, Coord::new(2, 1)).unwrap();
+        spatial_table.begin_step("edit");
+        spatial_table.update_layer(hero, Layer::Feature).unwrap();
+        spatial_table.clear_layer(wall).unwrap();
+        spatial_table.remove(wall);
+        spatial_table.end_step();
+        spatial_table.begin_step("empty");
+        spatial_table.end_step();
+
+        assert_eq!(spatial_table.undo(), Ok(Some("edit".to_string())));
+        assert_eq!(spatial_table.coord_of(wall), Some(Coord::new(0, 0)));
+        assert_eq!(
+            spatial_table.layers_at_checked(Coord::new(2, 1)).character,
+            Some(hero)
+        );
+        assert_eq!(spatial_table.undo(), Ok(Some("place hero".to_string())));
+        assert_eq!(spatial_table.coord_of(hero), None);
+        assert_eq!(spatial_table.iter_layer(Layer::Character).len(), 0);
+        assert_eq!(spatial_table.redo(), Ok(Some("place hero".to_string())));
+        assert_eq!(
+            spatial_table.layers_at_checked(Coord::new(2, 1)).character,
+            Some(hero)
+        );
+        assert_eq!(spatial_table.redo(), Ok(Some("edit".to_string())));
+        assert_eq!(spatial_table.coord_of(wall), None);
+        assert_eq!(
+            spatial_table.layers_at_checked(Coord::new(2, 1)).feature,
+            Some(hero)
+        );
+        assert!(!spatial_table.can_redo());
+
+        // making a change discards the redo steps
+        spatial_table.undo().unwrap();
+        assert!(spatial_table.can_redo());
+        spatial_table.update_coord(hero, Coord::new(3, 3)).unwrap();
+        assert!(!spatial_table.can_redo());
+        assert_eq!(spatial_table.undo(), Ok(Some(String::new())));
+        assert_eq!(spatial_table.coord_of(hero), Some(Coord::new(2, 1)));
+        assert_eq!(spatial_table.undo(), Ok(Some("place hero".to_string())));
+        assert_eq!(spatial_table.undo(), Ok(Some(String::new())));
+        assert_eq!(spatial_table.undo(), Ok(None));
+        assert_eq!(spatial_table.iter_entities().count(), 0);
+    }
+
+    #[test]
+    fn resize_discards_history() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let wall = entity_allocator.alloc();
+        let hero = entity_allocator.alloc();
+        spatial_table.enable_history();
+        spatial_table
+            .update(wall, (Coord::new(0, 0), Layer::Feature).into())
+            .unwrap();
+        spatial_table
+            .update(hero, (Coord::new(8, 8), Layer::Character).into())
+            .unwrap();
+        spatial_table.begin_step("move hero");
+        spatial_table.update_coord(hero, Coord::new(9, 9)).unwrap();
+        spatial_table.undo().unwrap();
+        assert!(spatial_table.can_redo());
+
+        // cropping removes the wall and moves the hero
+        assert_eq!(
+            spatial_table.resize(Size::new(5, 5), Coord::new(-5, -5)),
+            vec![wall]
+        );
+        assert!(!spatial_table.can_undo());
+        assert!(!spatial_table.can_redo());
+        assert_eq!(spatial_table.undo(), Ok(None));
+        assert_eq!(spatial_table.coord_of(hero), Some(Coord::new(3, 3)));
+
+        // changes after resizing are still recorded
+        spatial_table.update_coord(hero, Coord::new(4, 4)).unwrap();
+        assert_eq!(spatial_table.undo(), Ok(Some(String::new())));
+        assert_eq!(spatial_table.coord_of(hero), Some(Coord::new(3, 3)));
+        assert_eq!(spatial_table.check_invariants(), vec![]);
+    }
+}
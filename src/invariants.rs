@@ -0,0 +1,308 @@
+use crate::{Coord, Entity, Layers, Location, SpatialTable, Storage};
+
+/// A way in which the spatial grid of a [`SpatialTable`] disagrees with the locations of its
+/// entities
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvariantViolation<L> {
+    /// The entity is on a layer, but a coord it covers is outside the bounds of the spatial
+    /// grid
+    OutOfBounds { entity: Entity, coord: Coord },
+    /// The entity is on a layer, but the corresponding slot in a cell it covers doesn't contain
+    /// it
+    MissingFromGrid {
+        entity: Entity,
+        coord: Coord,
+        layer: L,
+    },
+    /// A slot in the spatial grid contains an entity which doesn't cover that cell, or which
+    /// has no location
+    OrphanedSlot {
+        entity: Entity,
+        coord: Coord,
+        layer: L,
+    },
+    /// A slot in the spatial grid contains an entity at the correct coord, but the entity's
+    /// location has a different layer
+    MismatchedLayer {
+        entity: Entity,
+        coord: Coord,
+        layer: L,
+        location_layer: Option<L>,
+    },
+    /// The slot of a stack layer doesn't contain the top of its stack
+    StackTopMismatch { coord: Coord, layer: L },
+    /// The per-layer index of entities disagrees with the entity's location
+    LayerIndexMismatch { entity: Entity, layer: L },
+    /// The occupancy bitmap of a layer disagrees with the spatial grid
+    OccupancyMismatch { coord: Coord, layer: L },
+}
+
+impl<L: Layers, S: Storage<L>> SpatialTable<L, S> {
+    /// Walks the locations of all entities and the contents of the spatial grid, returning
+    /// every inconsistency between them. The result is empty unless the table has been
+    /// corrupted.
+    pub fn check_invariants(&self) -> Vec<InvariantViolation<L::Layer>> {
+        let mut violations = Vec::new();
+        for (entity, location) in self.location_component.iter() {
+            let (coord, layer) = match *location {
+                Location {
+                    coord,
+                    layer: Some(layer),
+                } => (coord, layer),
+                Location { layer: None, .. } => continue,
+            };
+            let footprint = self.footprint_of(entity).cloned().unwrap_or_default();
+            for coord in footprint.coords(coord) {
+                let present = match self.spatial_grid.get(coord) {
+                    None => {
+                        violations.push(InvariantViolation::OutOfBounds { entity, coord });
+                        continue;
+                    }
+                    Some(_) if L::is_stack(layer) => {
+                        self.stack_at(coord, layer).any(|e| e == entity)
+                    }
+                    Some(cell) => *cell.select_field(layer) == Some(entity),
+                };
+                if !present {
+                    violations.push(InvariantViolation::MissingFromGrid {
+                        entity,
+                        coord,
+                        layer,
+                    });
+                }
+            }
+        }
+        self.spatial_grid.for_each_cell(|coord, cell| {
+            for &layer in L::LAYERS {
+                if let Some(entity) = *cell.select_field(layer) {
+                    if L::is_stack(layer) {
+                        if !self.stacks.contains_key(&(coord, layer)) {
+                            violations.push(InvariantViolation::StackTopMismatch { coord, layer });
+                        }
+                    } else if let Some(violation) = self.check_slot(entity, coord, layer) {
+                        violations.push(violation);
+                    }
+                }
+                if let Some(bitmap) = self.occupancy_bitmap(layer) {
+                    if bitmap.get(coord) != cell.select_field(layer).is_some() {
+                        violations.push(InvariantViolation::OccupancyMismatch { coord, layer });
+                    }
+                }
+            }
+        });
+        for (&(coord, layer), stack) in self.stacks.iter() {
+            let top = self
+                .spatial_grid
+                .get(coord)
+                .and_then(|cell| *cell.select_field(layer));
+            if top != stack.last().copied() {
+                violations.push(InvariantViolation::StackTopMismatch { coord, layer });
+            }
+            for &entity in stack {
+                if let Some(violation) = self.check_slot(entity, coord, layer) {
+                    violations.push(violation);
+                }
+            }
+        }
+        for &layer in L::LAYERS {
+            let layer_index = &self.layer_indices[L::layer_index(layer)];
+            for &entity in layer_index.entities() {
+                if self.layer_of(entity) != Some(layer) {
+                    violations.push(InvariantViolation::LayerIndexMismatch { entity, layer });
+                }
+            }
+        }
+        for (entity, location) in self.location_component.iter() {
+            if let Some(layer) = location.layer {
+                if !self.layer_indices[L::layer_index(layer)].contains(entity) {
+                    violations.push(InvariantViolation::LayerIndexMismatch { entity, layer });
+                }
+            }
+        }
+        violations
+    }
+    fn check_slot(
+        &self,
+        entity: Entity,
+        coord: Coord,
+        layer: L::Layer,
+    ) -> Option<InvariantViolation<L::Layer>> {
+        match self.location_of(entity) {
+            Some(location) if self.covers(entity, location.coord, coord) => {
+                if location.layer == Some(layer) {
+                    None
+                } else {
+                    Some(InvariantViolation::MismatchedLayer {
+                        entity,
+                        coord,
+                        layer,
+                        location_layer: location.layer,
+                    })
+                }
+            }
+            _ => Some(InvariantViolation::OrphanedSlot {
+                entity,
+                coord,
+                layer,
+            }),
+        }
+    }
+    /// Rebuilds the spatial grid from the locations of entities, treating the locations as
+    /// correct. Entities whose location is out of bounds or conflicts with another entity are
+    /// left at their coord but removed from their layer. Returns the violations which were
+    /// present before the repair. Changes made by repairing aren't reported to observers, the
+    /// journal or the history.
+    pub fn repair(&mut self) -> Vec<InvariantViolation<L::Layer>> {
+        let violations = self.check_invariants();
+        if violations.is_empty() {
+            return violations;
+        }
+        self.spatial_grid.clear();
+        self.stacks.clear();
+        self.clear_occupancy();
+        for layer_index in self.layer_indices.iter_mut() {
+            layer_index.clear();
+        }
+        let locations = self
+            .location_component
+            .iter()
+            .map(|(entity, &location)| (entity, location))
+            .collect::<Vec<_>>();
+        for (entity, location) in locations {
+            if let Some(layer) = location.layer {
+                if self.insert_entity(location.coord, entity, layer).is_ok() {
+                    self.layer_indices[L::layer_index(layer)].insert(entity);
+                } else {
+                    self.location_component.insert(
+                        entity,
+                        Location {
+                            layer: None,
+                            ..location
+                        },
+                    );
+                }
+            }
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod test {
+    crate::declare_layers_module! {
+        layers {
+            feature: Feature,
+            item: Item [stack],
+            character: Character,
+        }
+    }
+    use super::InvariantViolation;
+    use crate::{Coord, Location, Size};
+    use entity_table::EntityAllocator;
+    use layers::{Layer, Layers};
+    type SpatialTable = crate::SpatialTable<Layers>;
+
+    #[test]
+    fn check_and_repair() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let hero = entity_allocator.alloc();
+        let villain = entity_allocator.alloc();
+        let item = entity_allocator.alloc();
+        let ghost = entity_allocator.alloc();
+        spatial_table
+            .update(hero, (Coord::new(1, 1), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(villain, (Coord::new(2, 2), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(item, (Coord::new(1, 1), Layer::Item).into())
+            .unwrap();
+        spatial_table
+            .update_coord(ghost, Coord::new(20, 20))
+            .unwrap();
+        assert_eq!(spatial_table.check_invariants(), vec![]);
+        assert_eq!(spatial_table.repair(), vec![]);
+
+        // corrupt the table by changing locations without updating the grid
+        spatial_table.location_component.insert(
+            hero,
+            Location {
+                coord: Coord::new(1, 1),
+                layer: Some(Layer::Feature),
+            },
+        );
+        spatial_table
+            .location_component
+            .insert(villain, (Coord::new(3, 3), Layer::Character).into());
+        spatial_table
+            .location_component
+            .insert(ghost, (Coord::new(20, 20), Layer::Character).into());
+        let violations = spatial_table.check_invariants();
+        for violation in [
+            InvariantViolation::MissingFromGrid {
+                entity: hero,
+                coord: Coord::new(1, 1),
+                layer: Layer::Feature,
+            },
+            InvariantViolation::MissingFromGrid {
+                entity: villain,
+                coord: Coord::new(3, 3),
+                layer: Layer::Character,
+            },
+            InvariantViolation::OutOfBounds {
+                entity: ghost,
+                coord: Coord::new(20, 20),
+            },
+            InvariantViolation::MismatchedLayer {
+                entity: hero,
+                coord: Coord::new(1, 1),
+                layer: Layer::Character,
+                location_layer: Some(Layer::Feature),
+            },
+            InvariantViolation::OrphanedSlot {
+                entity: villain,
+                coord: Coord::new(2, 2),
+                layer: Layer::Character,
+            },
+            InvariantViolation::LayerIndexMismatch {
+                entity: hero,
+                layer: Layer::Feature,
+            },
+            InvariantViolation::LayerIndexMismatch {
+                entity: hero,
+                layer: Layer::Character,
+            },
+            InvariantViolation::LayerIndexMismatch {
+                entity: ghost,
+                layer: Layer::Character,
+            },
+        ] {
+            assert!(violations.contains(&violation), "{:?}", violation);
+        }
+        assert_eq!(violations.len(), 8);
+
+        assert_eq!(spatial_table.repair(), violations);
+        assert_eq!(spatial_table.check_invariants(), vec![]);
+        assert_eq!(
+            *spatial_table.layers_at_checked(Coord::new(1, 1)),
+            Layers {
+                feature: Some(hero),
+                item: Some(item),
+                character: None,
+            },
+        );
+        assert_eq!(
+            spatial_table.layers_at_checked(Coord::new(3, 3)).character,
+            Some(villain),
+        );
+        assert_eq!(
+            spatial_table.location_of(ghost),
+            Some(&Location {
+                coord: Coord::new(20, 20),
+                layer: None,
+            }),
+        );
+    }
+}
@@ -0,0 +1,436 @@
+use crate::{Coord, Entity, Footprint, Layers, Location, SpatialTable, Storage};
+use std::collections::HashSet;
+
+/// A change made to a [`SpatialTable`], recorded while its journal is enabled. Footprints are
+/// `None` for entities which cover only their anchor. Events which can change an entity's
+/// footprint record both the footprint it had before the change and the one it has after.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event<L> {
+    /// An entity with no location was given a location
+    Inserted {
+        entity: Entity,
+        location: Location<L>,
+        footprint: Option<Footprint>,
+    },
+    /// An entity's coord changed. Its layer may also have changed.
+    Moved {
+        entity: Entity,
+        from: Location<L>,
+        to: Location<L>,
+        from_footprint: Option<Footprint>,
+        to_footprint: Option<Footprint>,
+    },
+    /// An entity was placed on a layer, or moved to a different layer, without changing coord
+    LayerChanged {
+        entity: Entity,
+        coord: Coord,
+        from: Option<L>,
+        to: L,
+        from_footprint: Option<Footprint>,
+        to_footprint: Option<Footprint>,
+    },
+    /// An entity was removed from its layer without changing coord
+    LayerCleared {
+        entity: Entity,
+        coord: Coord,
+        layer: L,
+        from_footprint: Option<Footprint>,
+        to_footprint: Option<Footprint>,
+    },
+    /// The footprint of an entity changed without its location changing
+    FootprintChanged {
+        entity: Entity,
+        location: Location<L>,
+        from: Option<Footprint>,
+        to: Option<Footprint>,
+    },
+    /// An entity's location was removed
+    Removed {
+        entity: Entity,
+        location: Location<L>,
+        footprint: Option<Footprint>,
+    },
+    /// Every entity was removed. `coords` contains each coord covered by an entity which was
+    /// present.
+    Cleared { coords: Vec<Coord> },
+}
+
+/// The offsets covered by an entity with no footprint
+const SINGLE: &[Coord] = &[Coord::new(0, 0)];
+
+/// A coord occupied by the anchor of an entity, along with the entity's footprint
+type Anchor<'a> = Option<(Coord, &'a Option<Footprint>)>;
+
+impl<L> Event<L> {
+    /// Iterates over the coords whose contents were changed by this event, including every
+    /// cell covered by the entity before and after the change. A coord may appear more than
+    /// once.
+    pub fn coords(&self) -> impl Iterator<Item = Coord> + '_ {
+        let (anchors, rest): ([Anchor; 2], &[Coord]) = match self {
+            Self::Inserted {
+                location,
+                footprint,
+                ..
+            }
+            | Self::Removed {
+                location,
+                footprint,
+                ..
+            } => ([Some((location.coord, footprint)), None], &[]),
+            Self::Moved {
+                from,
+                to,
+                from_footprint,
+                to_footprint,
+                ..
+            } => (
+                [
+                    Some((from.coord, from_footprint)),
+                    Some((to.coord, to_footprint)),
+                ],
+                &[],
+            ),
+            Self::LayerChanged {
+                coord,
+                from_footprint,
+                to_footprint,
+                ..
+            }
+            | Self::LayerCleared {
+                coord,
+                from_footprint,
+                to_footprint,
+                ..
+            } => (
+                [Some((*coord, from_footprint)), Some((*coord, to_footprint))],
+                &[],
+            ),
+            Self::FootprintChanged {
+                location, from, to, ..
+            } => (
+                [Some((location.coord, from)), Some((location.coord, to))],
+                &[],
+            ),
+            Self::Cleared { coords } => ([None, None], coords),
+        };
+        anchors
+            .into_iter()
+            .flatten()
+            .flat_map(|(anchor, footprint)| {
+                let offsets = footprint.as_ref().map_or(SINGLE, Footprint::offsets);
+                offsets.iter().map(move |&offset| anchor + offset)
+            })
+            .chain(rest.iter().copied())
+    }
+    pub(crate) fn new(
+        entity: Entity,
+        original_location: Option<Location<L>>,
+        location: Option<Location<L>>,
+        original_footprint: Option<&Footprint>,
+        footprint: Option<&Footprint>,
+    ) -> Option<Self>
+    where
+        L: Copy + PartialEq,
+    {
+        let from_footprint = original_footprint.cloned();
+        let to_footprint = footprint.cloned();
+        match (original_location, location) {
+            (None, None) => None,
+            (None, Some(location)) => Some(Self::Inserted {
+                entity,
+                location,
+                footprint: to_footprint,
+            }),
+            (Some(location), None) => Some(Self::Removed {
+                entity,
+                location,
+                footprint: from_footprint,
+            }),
+            (Some(from), Some(to)) => {
+                if from.coord != to.coord {
+                    Some(Self::Moved {
+                        entity,
+                        from,
+                        to,
+                        from_footprint,
+                        to_footprint,
+                    })
+                } else {
+                    match (from.layer, to.layer) {
+                        (from_layer, Some(to_layer)) if from_layer != Some(to_layer) => {
+                            Some(Self::LayerChanged {
+                                entity,
+                                coord: to.coord,
+                                from: from_layer,
+                                to: to_layer,
+                                from_footprint,
+                                to_footprint,
+                            })
+                        }
+                        (Some(layer), None) => Some(Self::LayerCleared {
+                            entity,
+                            coord: to.coord,
+                            layer,
+                            from_footprint,
+                            to_footprint,
+                        }),
+                        _ if from_footprint != to_footprint => Some(Self::FootprintChanged {
+                            entity,
+                            location: to,
+                            from: from_footprint,
+                            to: to_footprint,
+                        }),
+                        _ => None,
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<L: Layers, S: Storage<L>> SpatialTable<L, S> {
+    /// Starts recording an [`Event`] for each change made to the table. Has no effect if the
+    /// journal is already enabled.
+    pub fn enable_journal(&mut self) {
+        if self.journal.is_none() {
+            self.journal = Some(Vec::new());
+        }
+    }
+    /// Stops recording events, discarding any events which haven't been drained
+    pub fn disable_journal(&mut self) {
+        self.journal = None;
+    }
+    pub fn is_journal_enabled(&self) -> bool {
+        self.journal.is_some()
+    }
+    /// The events recorded since the journal was enabled or last drained, in the order they
+    /// occurred
+    pub fn events(&self) -> &[Event<L::Layer>] {
+        self.journal.as_deref().unwrap_or(&[])
+    }
+    /// Removes and returns the recorded events, in the order they occurred
+    pub fn drain_events(&mut self) -> Vec<Event<L::Layer>> {
+        self.journal
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+    /// The set of coords whose contents were changed by the events which haven't been drained
+    pub fn dirty_coords(&self) -> HashSet<Coord> {
+        self.events().iter().flat_map(Event::coords).collect()
+    }
+    pub(crate) fn record_event(&mut self, event: Event<L::Layer>) {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.push(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    crate::declare_layers_module! {
+        layers {
+            feature: Feature,
+            character: Character,
+        }
+    }
+    use super::Event;
+    use crate::{Coord, Footprint, Location, Size};
+    use entity_table::EntityAllocator;
+    use layers::{Layer, Layers};
+    use std::collections::HashSet;
+    type SpatialTable = crate::SpatialTable<Layers>;
+
+    #[test]
+    fn journal() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let hero = entity_allocator.alloc();
+        let wall = entity_allocator.alloc();
+        spatial_table
+            .update(wall, (Coord::new(5, 5), Layer::Feature).into())
+            .unwrap();
+        spatial_table.enable_journal();
+        spatial_table
+            .update(hero, (Coord::new(1, 1), Layer::Character).into())
+            .unwrap();
+        spatial_table.update_coord(hero, Coord::new(2, 1)).unwrap();
+        spatial_table.update_layer(hero, Layer::Feature).unwrap();
+        spatial_table.clear_layer(hero).unwrap();
+        // failed and no-op updates aren't recorded
+        spatial_table
+            .update(hero, (Coord::new(5, 5), Layer::Feature).into())
+            .unwrap_err();
+        spatial_table.update_coord(hero, Coord::new(2, 1)).unwrap();
+        spatial_table.remove(hero);
+        assert_eq!(
+            spatial_table.dirty_coords(),
+            [Coord::new(1, 1), Coord::new(2, 1)]
+                .into_iter()
+                .collect::<HashSet<_>>(),
+        );
+        assert_eq!(
+            spatial_table.drain_events(),
+            vec![
+                Event::Inserted {
+                    entity: hero,
+                    location: (Coord::new(1, 1), Layer::Character).into(),
+                    footprint: None,
+                },
+                Event::Moved {
+                    entity: hero,
+                    from: (Coord::new(1, 1), Layer::Character).into(),
+                    to: (Coord::new(2, 1), Layer::Character).into(),
+                    from_footprint: None,
+                    to_footprint: None,
+                },
+                Event::LayerChanged {
+                    entity: hero,
+                    coord: Coord::new(2, 1),
+                    from: Some(Layer::Character),
+                    to: Layer::Feature,
+                    from_footprint: None,
+                    to_footprint: None,
+                },
+                Event::LayerCleared {
+                    entity: hero,
+                    coord: Coord::new(2, 1),
+                    layer: Layer::Feature,
+                    from_footprint: None,
+                    to_footprint: None,
+                },
+                Event::Removed {
+                    entity: hero,
+                    location: Location {
+                        coord: Coord::new(2, 1),
+                        layer: None,
+                    },
+                    footprint: None,
+                },
+            ],
+        );
+        assert!(spatial_table.events().is_empty());
+
+        spatial_table.clear();
+        assert_eq!(
+            spatial_table.events(),
+            &[Event::Cleared {
+                coords: vec![Coord::new(5, 5)],
+            }],
+        );
+        spatial_table.disable_journal();
+        spatial_table
+            .update(hero, (Coord::new(1, 1), Layer::Character).into())
+            .unwrap();
+        assert!(spatial_table.events().is_empty());
+    }
+
+    #[test]
+    fn footprints() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let boss = entity_allocator.alloc();
+        let shape = Footprint::rect(Size::new(2, 2));
+        spatial_table.set_footprint(boss, shape.clone()).unwrap();
+        spatial_table
+            .update(boss, (Coord::new(1, 1), Layer::Character).into())
+            .unwrap();
+        spatial_table.enable_journal();
+        spatial_table.update_coord(boss, Coord::new(2, 1)).unwrap();
+        let coords = |coords: &[(i32, i32)]| {
+            coords
+                .iter()
+                .map(|&(x, y)| Coord::new(x, y))
+                .collect::<HashSet<_>>()
+        };
+        assert_eq!(
+            spatial_table.dirty_coords(),
+            coords(&[(1, 1), (2, 1), (3, 1), (1, 2), (2, 2), (3, 2)]),
+        );
+        spatial_table.drain_events();
+
+        // the footprint is recorded, so removing the entity reports every cell it covered
+        spatial_table.remove(boss);
+        assert_eq!(
+            spatial_table.events(),
+            &[Event::Removed {
+                entity: boss,
+                location: (Coord::new(2, 1), Layer::Character).into(),
+                footprint: Some(shape.clone()),
+            }],
+        );
+        assert_eq!(
+            spatial_table.dirty_coords(),
+            coords(&[(2, 1), (3, 1), (2, 2), (3, 2)]),
+        );
+        spatial_table.drain_events();
+
+        spatial_table.set_footprint(boss, shape.clone()).unwrap();
+        spatial_table
+            .update(boss, (Coord::new(5, 5), Layer::Character).into())
+            .unwrap();
+        spatial_table.drain_events();
+        spatial_table.clear();
+        assert_eq!(
+            spatial_table.dirty_coords(),
+            coords(&[(5, 5), (6, 5), (5, 6), (6, 6)]),
+        );
+        spatial_table.drain_events();
+
+        // changing only the footprint reports the cells covered before and after
+        spatial_table
+            .update(boss, (Coord::new(1, 1), Layer::Character).into())
+            .unwrap();
+        spatial_table.drain_events();
+        let wide = Footprint::rect(Size::new(3, 1));
+        spatial_table.set_footprint(boss, wide.clone()).unwrap();
+        assert_eq!(
+            spatial_table.events(),
+            &[Event::FootprintChanged {
+                entity: boss,
+                location: (Coord::new(1, 1), Layer::Character).into(),
+                from: None,
+                to: Some(wide.clone()),
+            }],
+        );
+        assert_eq!(
+            spatial_table.dirty_coords(),
+            coords(&[(1, 1), (2, 1), (3, 1)])
+        );
+        spatial_table.drain_events();
+        spatial_table.clear_footprint(boss);
+        assert_eq!(
+            spatial_table.dirty_coords(),
+            coords(&[(1, 1), (2, 1), (3, 1)])
+        );
+        spatial_table.drain_events();
+
+        // a move which also changes the footprint reports the old footprint at the old coord
+        let mut transaction = spatial_table.transaction();
+        transaction.update_coord(boss, Coord::new(5, 5));
+        transaction.set_footprint(boss, wide.clone());
+        transaction.commit().unwrap();
+        assert_eq!(
+            spatial_table.events(),
+            &[Event::Moved {
+                entity: boss,
+                from: (Coord::new(1, 1), Layer::Character).into(),
+                to: (Coord::new(5, 5), Layer::Character).into(),
+                from_footprint: None,
+                to_footprint: Some(wide.clone()),
+            }],
+        );
+        assert_eq!(
+            spatial_table.dirty_coords(),
+            coords(&[(1, 1), (5, 5), (6, 5), (7, 5)]),
+        );
+        spatial_table.drain_events();
+        let mut transaction = spatial_table.transaction();
+        transaction.set_footprint(boss, shape);
+        transaction.commit().unwrap();
+        assert_eq!(
+            spatial_table.dirty_coords(),
+            coords(&[(5, 5), (6, 5), (7, 5), (5, 6), (6, 6)]),
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use crate::{Coord, Entity, Layers, Location, SpatialTable, Storage};
+use entity_table::{ComponentTable, ComponentTableIter};
+
+/// The set of entities on a single layer, supporting constant-time insertion and removal and
+/// iteration proportional to the number of entities on the layer
+#[derive(Debug, Default)]
+pub(crate) struct LayerIndex {
+    entities: Vec<Entity>,
+    positions: ComponentTable<usize>,
+}
+
+impl LayerIndex {
+    pub(crate) fn insert(&mut self, entity: Entity) {
+        if self.positions.insert(entity, self.entities.len()).is_none() {
+            self.entities.push(entity);
+        } else {
+            debug_assert!(false, "Entity is already in layer index");
+        }
+    }
+    pub(crate) fn remove(&mut self, entity: Entity) {
+        if let Some(position) = self.positions.remove(entity) {
+            self.entities.swap_remove(position);
+            if let Some(&moved_entity) = self.entities.get(position) {
+                self.positions.insert(moved_entity, position);
+            }
+        } else {
+            debug_assert!(false, "Entity is not in layer index");
+        }
+    }
+    pub(crate) fn clear(&mut self) {
+        self.entities.clear();
+        self.positions.clear();
+    }
+    pub(crate) fn contains(&self, entity: Entity) -> bool {
+        self.positions.contains(entity)
+    }
+    pub(crate) fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+}
+
+/// Iterates over the entities on a single layer, along with their coords
+pub struct LayerEntities<'a, T> {
+    entities: std::slice::Iter<'a, Entity>,
+    location_component: &'a ComponentTable<Location<T>>,
+}
+
+impl<'a, T> Iterator for LayerEntities<'a, T> {
+    type Item = (Entity, Coord);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entities.next().map(|&entity| {
+            let location = self
+                .location_component
+                .get(entity)
+                .expect("Entity in layer index has no location");
+            (entity, location.coord)
+        })
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entities.size_hint()
+    }
+}
+
+impl<'a, T> ExactSizeIterator for LayerEntities<'a, T> {}
+
+/// Iterates over every entity with a location, along with its coord
+pub struct EntityCoords<'a, T> {
+    iter: ComponentTableIter<'a, Location<T>>,
+}
+
+impl<'a, T> Iterator for EntityCoords<'a, T> {
+    type Item = (Entity, Coord);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter
+            .next()
+            .map(|(entity, location)| (entity, location.coord))
+    }
+}
+
+impl<L: Layers, S: Storage<L>> SpatialTable<L, S> {
+    /// Iterates over the entities on a layer. The time taken is proportional to the number of
+    /// entities on the layer rather than the size of the spatial grid.
+    pub fn iter_layer(&self, layer: L::Layer) -> LayerEntities<'_, L::Layer> {
+        LayerEntities {
+            entities: self.layer_indices[L::layer_index(layer)].entities().iter(),
+            location_component: &self.location_component,
+        }
+    }
+    /// Iterates over every entity with a location, including entities with no layer
+    pub fn iter_entities(&self) -> EntityCoords<'_, L::Layer> {
+        EntityCoords {
+            iter: self.location_component.iter(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    crate::declare_layers_module! {
+        layers {
+            feature: Feature,
+            item: Item [stack],
+            character: Character,
+        }
+    }
+    use crate::{Coord, Size};
+    use entity_table::EntityAllocator;
+    use layers::{Layer, Layers};
+    type SpatialTable = crate::SpatialTable<Layers>;
+
+    fn sorted<I: Iterator<Item = T>, T: Ord>(iter: I) -> Vec<T> {
+        let mut vec = iter.collect::<Vec<_>>();
+        vec.sort();
+        vec
+    }
+
+    #[test]
+    fn iter_layer() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(1000, 1000));
+        let hero = entity_allocator.alloc();
+        let villain = entity_allocator.alloc();
+        let item_a = entity_allocator.alloc();
+        let item_b = entity_allocator.alloc();
+        let ghost = entity_allocator.alloc();
+        spatial_table
+            .update(hero, (Coord::new(1, 2), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(villain, (Coord::new(900, 800), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(item_a, (Coord::new(1, 2), Layer::Item).into())
+            .unwrap();
+        spatial_table
+            .update(item_b, (Coord::new(1, 2), Layer::Item).into())
+            .unwrap();
+        spatial_table.update_coord(ghost, Coord::new(5, 5)).unwrap();
+
+        assert_eq!(
+            sorted(spatial_table.iter_layer(Layer::Character)),
+            vec![(hero, Coord::new(1, 2)), (villain, Coord::new(900, 800))],
+        );
+        assert_eq!(
+            sorted(spatial_table.iter_layer(Layer::Item)),
+            vec![(item_a, Coord::new(1, 2)), (item_b, Coord::new(1, 2))],
+        );
+        assert_eq!(spatial_table.iter_layer(Layer::Feature).len(), 0);
+        assert_eq!(spatial_table.iter_entities().count(), 5);
+
+        spatial_table.update_coord(hero, Coord::new(3, 3)).unwrap();
+        spatial_table.update_layer(villain, Layer::Feature).unwrap();
+        spatial_table.clear_layer(item_a).unwrap();
+        spatial_table.remove(item_b);
+        spatial_table.swap(hero, ghost).unwrap();
+        assert_eq!(
+            sorted(spatial_table.iter_layer(Layer::Character)),
+            vec![(ghost, Coord::new(3, 3))],
+        );
+        assert_eq!(
+            sorted(spatial_table.iter_layer(Layer::Feature)),
+            vec![(villain, Coord::new(900, 800))],
+        );
+        assert_eq!(spatial_table.iter_layer(Layer::Item).len(), 0);
+        assert_eq!(
+            sorted(spatial_table.iter_entities()),
+            vec![
+                (hero, Coord::new(5, 5)),
+                (villain, Coord::new(900, 800)),
+                (item_a, Coord::new(1, 2)),
+                (ghost, Coord::new(3, 3)),
+            ],
+        );
+
+        spatial_table.clear();
+        assert_eq!(spatial_table.iter_layer(Layer::Feature).len(), 0);
+        assert_eq!(spatial_table.iter_entities().count(), 0);
+    }
+}
@@ -0,0 +1,274 @@
+use crate::Layers;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::ops::{BitAnd, BitOr, Sub};
+
+/// A set of layers, stored as one bit per layer. The layers module generated by
+/// [`declare_layers_module`](crate::declare_layers_module) contains an alias `LayerSet` for the set of its layers, and
+/// layers can be combined into a set with `|`, e.g. `Layer::Feature | Layer::Character`.
+/// Supports up to 64 layers.
+pub struct LayerSet<L> {
+    bits: u64,
+    layers: PhantomData<fn() -> L>,
+}
+
+impl<L: Layers> LayerSet<L> {
+    pub const fn empty() -> Self {
+        Self {
+            bits: 0,
+            layers: PhantomData,
+        }
+    }
+    /// The set containing every layer in [`Layers::LAYERS`]
+    pub fn all() -> Self {
+        L::LAYERS
+            .iter()
+            .fold(Self::empty(), |set, &layer| set.with(layer))
+    }
+    pub fn single(layer: L::Layer) -> Self {
+        Self::empty().with(layer)
+    }
+    fn bit(layer: L::Layer) -> u64 {
+        let index = L::layer_index(layer);
+        assert!(index < 64, "LayerSet supports at most 64 layers");
+        1 << index
+    }
+    pub fn is_empty(self) -> bool {
+        self.bits == 0
+    }
+    pub fn len(self) -> usize {
+        self.bits.count_ones() as usize
+    }
+    pub fn contains(self, layer: L::Layer) -> bool {
+        self.bits & Self::bit(layer) != 0
+    }
+    pub fn insert(&mut self, layer: L::Layer) {
+        self.bits |= Self::bit(layer);
+    }
+    pub fn remove(&mut self, layer: L::Layer) {
+        self.bits &= !Self::bit(layer);
+    }
+    /// Returns a copy of the set with `layer` added
+    pub fn with(mut self, layer: L::Layer) -> Self {
+        self.insert(layer);
+        self
+    }
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            bits: self.bits | other.bits,
+            ..self
+        }
+    }
+    pub fn intersection(self, other: Self) -> Self {
+        Self {
+            bits: self.bits & other.bits,
+            ..self
+        }
+    }
+    pub fn difference(self, other: Self) -> Self {
+        Self {
+            bits: self.bits & !other.bits,
+            ..self
+        }
+    }
+    pub fn is_subset(self, other: Self) -> bool {
+        self.difference(other).is_empty()
+    }
+    pub fn intersects(self, other: Self) -> bool {
+        !self.intersection(other).is_empty()
+    }
+    /// Iterates over the layers in the set, in the order of [`Layers::LAYERS`]
+    pub fn iter(self) -> LayerSetIter<L> {
+        LayerSetIter {
+            set: self,
+            layers: L::LAYERS.iter(),
+        }
+    }
+}
+
+/// Iterator over the layers in a [`LayerSet`]
+pub struct LayerSetIter<L: Layers> {
+    set: LayerSet<L>,
+    layers: std::slice::Iter<'static, L::Layer>,
+}
+
+impl<L: Layers> Iterator for LayerSetIter<L> {
+    type Item = L::Layer;
+    fn next(&mut self) -> Option<Self::Item> {
+        let set = self.set;
+        self.layers
+            .by_ref()
+            .copied()
+            .find(|&layer| set.contains(layer))
+    }
+}
+
+impl<L: Layers> IntoIterator for LayerSet<L> {
+    type Item = L::Layer;
+    type IntoIter = LayerSetIter<L>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<L: Layers> FromIterator<L::Layer> for LayerSet<L> {
+    fn from_iter<I: IntoIterator<Item = L::Layer>>(iter: I) -> Self {
+        iter.into_iter().fold(Self::empty(), Self::with)
+    }
+}
+
+/// `None` means every layer, matching the convention of methods which take an optional layer
+impl<L: Layers> From<Option<L::Layer>> for LayerSet<L> {
+    fn from(layer: Option<L::Layer>) -> Self {
+        match layer {
+            Some(layer) => Self::single(layer),
+            None => Self::all(),
+        }
+    }
+}
+
+impl<L: Layers> BitOr for LayerSet<L> {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self {
+        self.union(rhs)
+    }
+}
+
+impl<L: Layers> BitAnd for LayerSet<L> {
+    type Output = Self;
+    fn bitand(self, rhs: Self) -> Self {
+        self.intersection(rhs)
+    }
+}
+
+impl<L: Layers> Sub for LayerSet<L> {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        self.difference(rhs)
+    }
+}
+
+impl<L> Clone for LayerSet<L> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<L> Copy for LayerSet<L> {}
+
+impl<L> PartialEq for LayerSet<L> {
+    fn eq(&self, other: &Self) -> bool {
+        self.bits == other.bits
+    }
+}
+
+impl<L> Eq for LayerSet<L> {}
+
+impl<L> Hash for LayerSet<L> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.bits.hash(state);
+    }
+}
+
+impl<L: Layers> Default for LayerSet<L> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl<L: Layers> fmt::Debug for LayerSet<L>
+where
+    L::Layer: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    crate::declare_layers_module! {
+        layers {
+            floor: Floor,
+            feature: Feature,
+            item: Item [stack],
+            character: Character,
+        }
+    }
+    use super::LayerSet;
+    use crate::{Coord, Size};
+    use entity_table::EntityAllocator;
+    use layers::{Layer, Layers};
+    type SpatialTable = crate::SpatialTable<Layers>;
+
+    #[test]
+    fn set_operations() {
+        let solid = Layer::Feature | Layer::Character;
+        assert!(solid.contains(Layer::Feature));
+        assert!(!solid.contains(Layer::Floor));
+        assert_eq!(solid.len(), 2);
+        assert_eq!(
+            solid.iter().collect::<Vec<_>>(),
+            vec![Layer::Feature, Layer::Character],
+        );
+        let visible = Layer::Floor | Layer::Item | Layer::Character;
+        assert_eq!(solid & visible, LayerSet::single(Layer::Character));
+        assert_eq!(solid | visible, layers::LayerSet::all());
+        assert_eq!(solid - visible, Layer::Feature.into());
+        assert!(LayerSet::single(Layer::Character).is_subset(solid));
+        assert!(!solid.intersects(Layer::Floor.into()));
+        assert!((solid - solid).is_empty());
+        assert_eq!(
+            [Layer::Character, Layer::Feature]
+                .into_iter()
+                .collect::<layers::LayerSet>(),
+            solid,
+        );
+        assert_eq!(layers::LayerSet::from(None), layers::LayerSet::all());
+        assert_eq!(format!("{:?}", solid), "{Feature, Character}");
+    }
+
+    #[test]
+    fn queries() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let floor = entity_allocator.alloc();
+        let wall = entity_allocator.alloc();
+        let item = entity_allocator.alloc();
+        let hero = entity_allocator.alloc();
+        spatial_table
+            .update(floor, (Coord::new(1, 1), Layer::Floor).into())
+            .unwrap();
+        spatial_table
+            .update(item, (Coord::new(1, 1), Layer::Item).into())
+            .unwrap();
+        spatial_table
+            .update(hero, (Coord::new(1, 1), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(wall, (Coord::new(2, 1), Layer::Feature).into())
+            .unwrap();
+        let solid = Layer::Feature | Layer::Character;
+        assert!(spatial_table.is_occupied_in(Coord::new(1, 1), solid));
+        assert!(spatial_table.is_occupied_in(Coord::new(2, 1), solid));
+        assert!(!spatial_table.is_occupied_in(Coord::new(1, 1), Layer::Feature));
+        assert!(!spatial_table.is_occupied_in(Coord::new(3, 1), solid));
+        assert!(!spatial_table.is_occupied_in(Coord::new(-1, 1), solid));
+        assert_eq!(
+            spatial_table
+                .entities_at(Coord::new(1, 1), Layer::Floor | Layer::Item)
+                .collect::<Vec<_>>(),
+            vec![(Layer::Floor, floor), (Layer::Item, item)],
+        );
+        assert_eq!(
+            spatial_table
+                .entities_in_rect(Coord::new(0, 0), Size::new(10, 10), solid)
+                .collect::<Vec<_>>(),
+            vec![
+                (Coord::new(1, 1), Layer::Character, hero),
+                (Coord::new(2, 1), Layer::Feature, wall),
+            ],
+        );
+    }
+}
@@ -8,162 +8,334 @@ pub use grid_2d::{Coord, Size};
 pub use serde; // public so it can be referenced in macro body
 #[cfg(feature = "serialize")]
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "derive")]
+pub use spatial_table_derive::Layers;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+#[cfg(feature = "serialize")]
+pub mod compact;
+mod footprint;
+mod history;
+mod invariants;
+mod journal;
+mod layer_index;
+mod layer_set;
+mod nearest;
+mod observer;
+mod occupancy;
+mod region;
+mod simultaneous;
+mod snapshot;
+mod storage;
+mod text;
+mod transaction;
+pub use footprint::Footprint;
+use history::History;
+pub use invariants::InvariantViolation;
+pub use journal::Event;
+use layer_index::LayerIndex;
+pub use layer_index::{EntityCoords, LayerEntities};
+pub use layer_set::{LayerSet, LayerSetIter};
+use observer::Observers;
+pub use observer::{ObserverId, SpatialObserver};
+pub use occupancy::{OccupancyBitmap, OccupiedCoords};
+pub use region::{EntitiesIn, Metric, RadiusCoords, RectCoords};
+pub use simultaneous::MoveConflict;
+pub use snapshot::{Diff, DiffEntry, Snapshot};
+pub use storage::{Chunked, Storage, CHUNK_SIZE};
+pub use text::FromTextError;
+pub use transaction::{Transaction, TransactionConflict};
 
 pub trait Layers: Default {
-    type Layer: Copy + PartialEq + Eq;
+    type Layer: Copy + PartialEq + Eq + Hash + 'static;
+
+    /// Every layer, in the order they were declared
+    const LAYERS: &'static [Self::Layer];
+
+    fn select_field(&self, layer: Self::Layer) -> &Option<Entity>;
     fn select_field_mut(&mut self, layer: Self::Layer) -> &mut Option<Entity>;
+
+    /// The position of a layer in [`Self::LAYERS`]
+    fn layer_index(layer: Self::Layer) -> usize {
+        Self::LAYERS
+            .iter()
+            .position(|&l| l == layer)
+            .expect("layer missing from LAYERS")
+    }
+
+    /// A name for a layer which is stable when layers are added, removed or reordered. Layers
+    /// are serialized by name.
+    fn layer_name(layer: Self::Layer) -> &'static str;
+
+    /// The layer with the given name, if any
+    fn layer_from_name(name: &str) -> Option<Self::Layer> {
+        Self::LAYERS
+            .iter()
+            .copied()
+            .find(|&layer| Self::layer_name(layer) == name)
+    }
+
+    /// Stack layers can hold any number of entities in a single cell. The field of a stack layer
+    /// holds the most recently added entity, and the full stack can be read with
+    /// [`LayersAt::stack`].
+    fn is_stack(_layer: Self::Layer) -> bool {
+        false
+    }
+
+    /// Iterates over the layers which contain an entity, along with the entity, in the order
+    /// of [`Self::LAYERS`]
+    fn occupied(&self) -> Occupied<'_, Self>
+    where
+        Self: Sized,
+    {
+        Occupied {
+            layers: self,
+            iter: Self::LAYERS.iter(),
+        }
+    }
 }
 
-#[cfg(not(feature = "serialize"))]
-#[macro_export]
-macro_rules! declare_layers_module {
-    { $module_name:ident { $($field_name:ident: $variant_name:ident,)* } } => {
-        mod $module_name {
-            #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-            pub struct LayerTable<T> {
-                $(pub $field_name: T,)*
+/// Iterator over the occupied layers of a cell, returned by [`Layers::occupied`]
+pub struct Occupied<'a, L: Layers> {
+    layers: &'a L,
+    iter: std::slice::Iter<'static, L::Layer>,
+}
+
+impl<'a, L: Layers> Iterator for Occupied<'a, L> {
+    type Item = (L::Layer, Entity);
+    fn next(&mut self) -> Option<Self::Item> {
+        for &layer in self.iter.by_ref() {
+            if let Some(entity) = *self.layers.select_field(layer) {
+                return Some((layer, entity));
             }
+        }
+        None
+    }
+}
 
-            pub type Layers = LayerTable<Option<$crate::Entity>>;
+/// The error returned when parsing a layer from a string which isn't the name of a layer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseLayerError(pub String);
 
-            #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-            pub enum Layer {
-                $($variant_name,)*
+impl std::fmt::Display for ParseLayerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "unknown layer {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseLayerError {}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __layer_kind_is_stack {
+    () => {
+        false
+    };
+    (stack) => {
+        true
+    };
+}
+
+/// The items of a module declared with `declare_layers_module!` which don't depend on
+/// whether the "serialize" feature is enabled. `LayerTable` additionally derives each of the
+/// bracketed traits.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __declare_layers_module_body {
+    { [$($table_derive:path),*] { $($field_name:ident: $variant_name:ident $([$kind:ident])?,)* } } => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq $(, $table_derive)*)]
+        pub struct LayerTable<T> {
+            $(pub $field_name: T,)*
+        }
+
+        pub type Layers = LayerTable<Option<$crate::Entity>>;
+
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum Layer {
+            $($variant_name,)*
+        }
+
+        impl Layer {
+            /// The number of layers
+            #[allow(unused)]
+            pub const COUNT: usize = [$(Layer::$variant_name,)*].len();
+
+            /// Every layer, in the order they were declared
+            #[allow(unused)]
+            pub const ALL: [Layer; Self::COUNT] = [$(Layer::$variant_name,)*];
+
+            /// The position of the layer in [`Self::ALL`]
+            #[allow(unused)]
+            pub fn index(self) -> usize {
+                self as usize
             }
 
-            impl<T> Default for LayerTable<Option<T>> {
-                fn default() -> Self {
-                    Self {
-                        $($field_name: None,)*
-                    }
-                }
+            #[allow(unused)]
+            pub fn from_index(index: usize) -> Option<Self> {
+                Self::ALL.get(index).copied()
             }
 
-            impl $crate::Layers for Layers {
-                type Layer = Layer;
-                fn select_field_mut(&mut self, layer: Self::Layer) -> &mut Option<$crate::Entity> {
-                    match layer {
-                        $(Layer::$variant_name => &mut self.$field_name,)*
-                    }
+            #[allow(unused)]
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Layer::$variant_name => stringify!($variant_name),)*
                 }
             }
 
-            impl<T> LayerTable<T> {
-                #[allow(unused)]
-                pub fn map<U, F: FnMut(&T) -> U>(&self, mut f: F) -> LayerTable<U> {
-                    LayerTable {
-                        $($field_name: f(&self.$field_name),)*
-                    }
+            #[allow(unused)]
+            pub fn from_name(name: &str) -> Option<Self> {
+                match name {
+                    $(stringify!($variant_name) => Some(Layer::$variant_name),)*
+                    _ => None,
                 }
+            }
+        }
 
-                #[allow(unused)]
-                pub fn for_each<F: FnMut(&T)>(&self, mut f: F) {
-                    $(f(&self.$field_name);)*
-                }
+        impl ::std::fmt::Display for Layer {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                f.write_str(self.name())
+            }
+        }
 
-                #[allow(unused)]
-                pub fn for_each_enumerate<F: FnMut(&T, Layer)>(&self, mut f: F) {
-                    $(f(&self.$field_name, Layer::$variant_name);)*
-                }
+        impl ::std::str::FromStr for Layer {
+            type Err = $crate::ParseLayerError;
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                Self::from_name(s).ok_or_else(|| $crate::ParseLayerError(s.to_string()))
             }
+        }
 
-            impl<T> LayerTable<Option<T>> {
-                #[allow(unused)]
-                pub fn option_map<U, F: FnMut(&T) -> U>(&self, mut f: F) -> LayerTable<Option<U>> {
-                    self.map(|ot| ot.as_ref().map(|t| f(t)))
-                }
+        pub type LayerSet = $crate::LayerSet<Layers>;
 
-                #[allow(unused)]
-                pub fn option_and_then<U, F: FnMut(&T) -> Option<U>>(&self, mut f: F) -> LayerTable<Option<U>> {
-                    self.map(|ot| ot.as_ref().and_then(|t| f(t)))
-                }
+        impl From<Layer> for LayerSet {
+            fn from(layer: Layer) -> Self {
+                LayerSet::single(layer)
+            }
+        }
 
-                #[allow(unused)]
-                pub fn option_for_each<F: FnMut(&T)>(&self, mut f: F) {
-                    $(if let Some(t) = self.$field_name.as_ref() { f(t); })*
-                }
+        impl ::std::ops::BitOr for Layer {
+            type Output = LayerSet;
+            fn bitor(self, rhs: Layer) -> LayerSet {
+                LayerSet::single(self).with(rhs)
+            }
+        }
 
-                #[allow(unused)]
-                pub fn option_for_each_enumerate<F: FnMut(&T, Layer)>(&self, mut f: F) {
-                    $(if let Some(t) = self.$field_name.as_ref() { f(t, Layer::$variant_name); })*
+        impl ::std::ops::BitOr<Layer> for LayerSet {
+            type Output = LayerSet;
+            fn bitor(self, rhs: Layer) -> LayerSet {
+                self.with(rhs)
+            }
+        }
+
+        impl<T> Default for LayerTable<Option<T>> {
+            fn default() -> Self {
+                Self {
+                    $($field_name: None,)*
                 }
             }
         }
-    }
-}
 
-#[cfg(feature = "serialize")]
-#[macro_export]
-macro_rules! declare_layers_module {
-    { $module_name:ident { $($field_name:ident: $variant_name:ident,)* } } => {
-        mod $module_name {
-            #[derive(Debug, Clone, Copy, PartialEq, Eq, $crate::serde::Serialize, $crate::serde::Deserialize)]
-            pub struct LayerTable<T> {
-                $(pub $field_name: T,)*
+        impl $crate::Layers for Layers {
+            type Layer = Layer;
+            const LAYERS: &'static [Layer] = &Layer::ALL;
+            fn select_field(&self, layer: Self::Layer) -> &Option<$crate::Entity> {
+                match layer {
+                    $(Layer::$variant_name => &self.$field_name,)*
+                }
+            }
+            fn select_field_mut(&mut self, layer: Self::Layer) -> &mut Option<$crate::Entity> {
+                match layer {
+                    $(Layer::$variant_name => &mut self.$field_name,)*
+                }
+            }
+            fn layer_index(layer: Self::Layer) -> usize {
+                layer.index()
             }
+            fn layer_name(layer: Self::Layer) -> &'static str {
+                layer.name()
+            }
+            fn is_stack(layer: Self::Layer) -> bool {
+                match layer {
+                    $(Layer::$variant_name => $crate::__layer_kind_is_stack!($($kind)?),)*
+                }
+            }
+        }
 
-            pub type Layers = LayerTable<Option<$crate::Entity>>;
+        impl<T> LayerTable<T> {
+            #[allow(unused)]
+            pub fn map<U, F: FnMut(&T) -> U>(&self, mut f: F) -> LayerTable<U> {
+                LayerTable {
+                    $($field_name: f(&self.$field_name),)*
+                }
+            }
 
-            #[derive(Debug, Clone, Copy, PartialEq, Eq, $crate::serde::Serialize, $crate::serde::Deserialize)]
-            pub enum Layer {
-                $($variant_name,)*
+            #[allow(unused)]
+            pub fn for_each<F: FnMut(&T)>(&self, mut f: F) {
+                $(f(&self.$field_name);)*
             }
 
-            impl<T> Default for LayerTable<Option<T>> {
-                fn default() -> Self {
-                    Self {
-                        $($field_name: None,)*
-                    }
-                }
+            #[allow(unused)]
+            pub fn for_each_enumerate<F: FnMut(&T, Layer)>(&self, mut f: F) {
+                $(f(&self.$field_name, Layer::$variant_name);)*
             }
+        }
 
-            impl $crate::Layers for Layers {
-                type Layer = Layer;
-                fn select_field_mut(&mut self, layer: Self::Layer) -> &mut Option<$crate::Entity> {
-                    match layer {
-                        $(Layer::$variant_name => &mut self.$field_name,)*
-                    }
-                }
+        impl<T> LayerTable<Option<T>> {
+            #[allow(unused)]
+            pub fn option_map<U, F: FnMut(&T) -> U>(&self, mut f: F) -> LayerTable<Option<U>> {
+                self.map(|ot| ot.as_ref().map(|t| f(t)))
             }
 
-            impl<T> LayerTable<T> {
-                #[allow(unused)]
-                pub fn map<U, F: FnMut(&T) -> U>(&self, mut f: F) -> LayerTable<U> {
-                    LayerTable {
-                        $($field_name: f(&self.$field_name),)*
-                    }
-                }
+            #[allow(unused)]
+            pub fn option_and_then<U, F: FnMut(&T) -> Option<U>>(&self, mut f: F) -> LayerTable<Option<U>> {
+                self.map(|ot| ot.as_ref().and_then(|t| f(t)))
+            }
 
-                #[allow(unused)]
-                pub fn for_each<F: FnMut(&T)>(&self, mut f: F) {
-                    $(f(&self.$field_name);)*
-                }
+            #[allow(unused)]
+            pub fn option_for_each<F: FnMut(&T)>(&self, mut f: F) {
+                $(if let Some(t) = self.$field_name.as_ref() { f(t); })*
+            }
 
-                #[allow(unused)]
-                pub fn for_each_enumerate<F: FnMut(&T, Layer)>(&self, mut f: F) {
-                    $(f(&self.$field_name, Layer::$variant_name);)*
-                }
+            #[allow(unused)]
+            pub fn option_for_each_enumerate<F: FnMut(&T, Layer)>(&self, mut f: F) {
+                $(if let Some(t) = self.$field_name.as_ref() { f(t, Layer::$variant_name); })*
             }
+        }
+    }
+}
 
-            impl<T> LayerTable<Option<T>> {
-                #[allow(unused)]
-                pub fn option_map<U, F: FnMut(&T) -> U>(&self, mut f: F) -> LayerTable<Option<U>> {
-                    self.map(|ot| ot.as_ref().map(|t| f(t)))
-                }
+#[cfg(not(feature = "serialize"))]
+#[macro_export]
+macro_rules! declare_layers_module {
+    { $module_name:ident { $($field_name:ident: $variant_name:ident $([$kind:ident])?,)* } } => {
+        mod $module_name {
+            $crate::__declare_layers_module_body! {
+                [] { $($field_name: $variant_name $([$kind])?,)* }
+            }
+        }
+    }
+}
 
-                #[allow(unused)]
-                pub fn option_and_then<U, F: FnMut(&T) -> Option<U>>(&self, mut f: F) -> LayerTable<Option<U>> {
-                    self.map(|ot| ot.as_ref().and_then(|t| f(t)))
-                }
+#[cfg(feature = "serialize")]
+#[macro_export]
+macro_rules! declare_layers_module {
+    { $module_name:ident { $($field_name:ident: $variant_name:ident $([$kind:ident])?,)* } } => {
+        mod $module_name {
+            $crate::__declare_layers_module_body! {
+                [$crate::serde::Serialize, $crate::serde::Deserialize]
+                { $($field_name: $variant_name $([$kind])?,)* }
+            }
 
-                #[allow(unused)]
-                pub fn option_for_each<F: FnMut(&T)>(&self, mut f: F) {
-                    $(if let Some(t) = self.$field_name.as_ref() { f(t); })*
+            impl $crate::serde::Serialize for Layer {
+                fn serialize<S: $crate::serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                    s.serialize_str(self.name())
                 }
+            }
 
-                #[allow(unused)]
-                pub fn option_for_each_enumerate<F: FnMut(&T, Layer)>(&self, mut f: F) {
-                    $(if let Some(t) = self.$field_name.as_ref() { f(t, Layer::$variant_name); })*
+            impl<'a> $crate::serde::Deserialize<'a> for Layer {
+                fn deserialize<D: $crate::serde::Deserializer<'a>>(d: D) -> Result<Self, D::Error> {
+                    let name = <String as $crate::serde::Deserialize>::deserialize(d)?;
+                    name.parse().map_err(<D::Error as $crate::serde::de::Error>::custom)
                 }
             }
         }
@@ -187,39 +359,407 @@ impl<L> From<(Coord, L)> for Location<L> {
 }
 
 #[derive(Debug)]
-pub struct SpatialTable<L: Layers> {
+pub struct SpatialTable<L: Layers, S: Storage<L> = Grid<L>> {
     location_component: ComponentTable<Location<L::Layer>>,
-    spatial_grid: Grid<L>,
+    spatial_grid: S,
+    stacks: Stacks<L::Layer>,
+    layer_indices: Vec<LayerIndex>,
+    journal: Option<Vec<Event<L::Layer>>>,
+    observers: Observers<L::Layer>,
+    history: Option<History<L::Layer>>,
+    occupancy: Option<Vec<OccupancyBitmap>>,
+    /// Footprints of entities which cover more than one cell
+    footprints: ComponentTable<Footprint>,
 }
 
+/// A [`SpatialTable`] with no fixed bounds, which only allocates storage for the parts of the
+/// plane that contain entities
+pub type ChunkedSpatialTable<L> = SpatialTable<L, Chunked<L>>;
+
 pub type Enumerate<'a, L> = grid_2d::GridEnumerate<'a, L>;
+pub type Stack<'a> = std::iter::Copied<std::slice::Iter<'a, Entity>>;
+
+/// The contents of a single cell, as returned by [`SpatialTable::layers_at`]. Dereferences to
+/// the cell's layers, which hold the top entity of each stack layer, and iterates over the whole
+/// of a stack with [`Self::stack`].
+pub struct LayersAt<'a, L: Layers> {
+    layers: &'a L,
+    stacks: &'a Stacks<L::Layer>,
+    coord: Coord,
+}
+
+impl<'a, L: Layers> LayersAt<'a, L> {
+    pub fn layers(&self) -> &'a L {
+        self.layers
+    }
+    /// Iterates over the entities on a stack layer, from the bottom of the stack to the top.
+    /// Yields nothing for layers which aren't stack layers.
+    pub fn stack(&self, layer: L::Layer) -> Stack<'a> {
+        stack(self.stacks, self.coord, layer)
+    }
+}
+
+impl<'a, L: Layers> Clone for LayersAt<'a, L> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, L: Layers> Copy for LayersAt<'a, L> {}
+
+impl<'a, L: Layers> std::ops::Deref for LayersAt<'a, L> {
+    type Target = L;
+    fn deref(&self) -> &L {
+        self.layers
+    }
+}
+
+impl<'a, L: Layers + std::fmt::Debug> std::fmt::Debug for LayersAt<'a, L> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.layers.fmt(f)
+    }
+}
 
 impl<L: Layers> SpatialTable<L> {
     pub fn new(size: Size) -> Self {
-        let location_component = ComponentTable::default();
-        let spatial_grid = Grid::new_default(size);
+        Self::with_storage(Grid::new_default(size))
+    }
+    pub fn enumerate(&self) -> Enumerate<'_, L> {
+        self.spatial_grid.enumerate()
+    }
+    pub fn grid_size(&self) -> Size {
+        self.spatial_grid.size()
+    }
+    /// Changes the size of the spatial grid, adding `offset` to the coord of every entity. Any
+    /// entities on a layer which would cover a cell outside the new grid are removed from the
+    /// table, and returned. Entities with no layer may lie outside the grid, so they're only
+    /// removed if adding `offset` to their coord would overflow. Resizing can't be undone, so if
+    /// history is enabled all undo and redo steps are discarded.
+    pub fn resize(&mut self, size: Size, offset: Coord) -> Vec<Entity> {
+        let removed = self
+            .location_component
+            .iter()
+            .filter(|&(entity, location)| {
+                let coord = match location.coord.checked_add(offset) {
+                    Some(coord) => coord,
+                    None => return true,
+                };
+                if location.layer.is_none() {
+                    return false;
+                }
+                match self.footprints.get(entity) {
+                    Some(footprint) => footprint.coords(coord).any(|coord| !coord.is_valid(size)),
+                    None => !coord.is_valid(size),
+                }
+            })
+            .map(|(entity, _)| entity)
+            .collect::<Vec<_>>();
+        for &entity in &removed {
+            self.remove(entity);
+        }
+        let mut spatial_grid = Grid::new_default(size);
+        for (coord, cell) in self.spatial_grid.enumerate_mut() {
+            if let Some(new_cell) = coord
+                .checked_add(offset)
+                .and_then(|coord| spatial_grid.get_mut(coord))
+            {
+                *new_cell = std::mem::take(cell);
+            }
+        }
+        self.spatial_grid = spatial_grid;
+        self.stacks = self
+            .stacks
+            .drain()
+            .map(|((coord, layer), stack)| ((coord + offset, layer), stack))
+            .collect();
+        if self.has_occupancy_bitmaps() {
+            self.build_occupancy_bitmaps();
+        }
+        if offset != Coord::new(0, 0) {
+            let moved = self
+                .location_component
+                .iter_mut()
+                .map(|(entity, location)| {
+                    let original_location = *location;
+                    location.coord += offset;
+                    (entity, original_location, *location)
+                })
+                .collect::<Vec<_>>();
+            for (entity, original_location, location) in moved {
+                self.location_changed(entity, Some(original_location), Some(location));
+            }
+        }
+        if let Some(history) = self.history.as_mut() {
+            *history = History::default();
+        }
+        removed
+    }
+    pub fn layers_at_checked(&self, coord: Coord) -> &L {
+        self.spatial_grid.get_checked(coord)
+    }
+    #[cfg(feature = "serialize")]
+    fn to_serialize(&self) -> SpatialSerialize<L::Layer> {
+        SpatialSerialize {
+            entries: self.location_component.entries().clone(),
+            size: self.spatial_grid.size(),
+            stack_order: self.stacks.values().flatten().copied().collect(),
+            footprints: self
+                .footprints
+                .iter()
+                .map(|(entity, footprint)| (entity, footprint.clone()))
+                .collect(),
+        }
+    }
+    #[cfg(feature = "serialize")]
+    fn from_serialize(
+        SpatialSerialize {
+            entries,
+            size,
+            stack_order,
+            footprints,
+        }: SpatialSerialize<L::Layer>,
+    ) -> Result<Self, Vec<ValidationError>> {
+        Self::from_location_component(
+            size,
+            entries.into_component_table(),
+            &stack_order,
+            footprints,
+        )
+    }
+    /// Entities on stack layers are added to their stacks in the order they appear in
+    /// `stack_order`, followed by any which don't appear in `stack_order` in the order they
+    /// appear in `location_component`. Each entity in `footprints` covers the cells of its
+    /// footprint, and must have a location.
+    #[cfg(feature = "serialize")]
+    fn from_location_component(
+        size: Size,
+        location_component: ComponentTable<Location<L::Layer>>,
+        stack_order: &[Entity],
+        footprints: Vec<(Entity, Footprint)>,
+    ) -> Result<Self, Vec<ValidationError>> {
+        let mut spatial_table = Self::new(size);
+        let mut errors = Vec::new();
+        for (entity, footprint) in footprints {
+            if !location_component.contains(entity) {
+                errors.push(ValidationError::FootprintWithoutLocation { entity });
+            } else if !footprint.is_single() {
+                spatial_table.footprints.insert(entity, footprint);
+            }
+        }
+        let stack_positions = stack_order
+            .iter()
+            .enumerate()
+            .map(|(position, &entity)| (entity, position))
+            .collect::<HashMap<_, _>>();
+        let mut locations = location_component
+            .iter()
+            .map(|(entity, &location)| (entity, location))
+            .collect::<Vec<_>>();
+        // stable, so entities missing from `stack_order` stay in their original order
+        locations.sort_by_key(|&(entity, location)| match location.layer {
+            Some(layer) if L::is_stack(layer) => {
+                stack_positions.get(&entity).copied().unwrap_or(usize::MAX)
+            }
+            _ => 0,
+        });
+        for (entity, location) in locations {
+            if let Some(layer) = location.layer {
+                let error = match spatial_table.insert_entity(location.coord, entity, layer) {
+                    Ok(()) => {
+                        spatial_table.layer_indices[L::layer_index(layer)].insert(entity);
+                        continue;
+                    }
+                    Err(error) => error,
+                };
+                // report the cell where the conflict happened rather than the anchor
+                let coord = spatial_table
+                    .covered_coords(entity, location.coord)
+                    .into_iter()
+                    .find(
+                        |&coord| match (error, spatial_table.spatial_grid.get(coord)) {
+                            (UpdateError::DestinationOutOfBounds, cell) => cell.is_none(),
+                            (UpdateError::OccupiedBy(occupant), Some(cell)) => {
+                                *cell.select_field(layer) == Some(occupant)
+                            }
+                            (UpdateError::OccupiedBy(_), None) => false,
+                        },
+                    )
+                    .unwrap_or(location.coord);
+                errors.push(match error {
+                    UpdateError::OccupiedBy(occupant) => ValidationError::OccupiedBy {
+                        entity,
+                        occupant,
+                        coord,
+                    },
+                    UpdateError::DestinationOutOfBounds => {
+                        ValidationError::OutOfBounds { entity, coord }
+                    }
+                });
+            }
+        }
+        if errors.is_empty() {
+            spatial_table.location_component = location_component;
+            Ok(spatial_table)
+        } else {
+            Err(errors)
+        }
+    }
+    /// Deserializes a spatial table and returns every problem which would prevent it from being
+    /// loaded, without panicking or stopping at the first problem. An empty result means the
+    /// data can be deserialized into a [`SpatialTable`].
+    #[cfg(feature = "serialize")]
+    pub fn validate<'a, D: serde::Deserializer<'a>>(d: D) -> Result<Vec<ValidationError>, D::Error>
+    where
+        L::Layer: Deserialize<'a>,
+    {
+        Deserialize::deserialize(d).map(|s| Self::from_serialize(s).err().unwrap_or_default())
+    }
+    /// Deserializes a spatial table whose layers may have been renamed or removed since it was
+    /// saved. `migrate` is called with the name of each layer which isn't recognised, and
+    /// returns the layer to use in its place, or `None` to drop entities on that layer from the
+    /// table. Returns the loaded table along with a report of every entity that was migrated.
+    #[cfg(feature = "serialize")]
+    pub fn deserialize_with_migration<'a, D, F>(
+        d: D,
+        mut migrate: F,
+    ) -> Result<(Self, MigrationReport<L::Layer>), D::Error>
+    where
+        D: serde::Deserializer<'a>,
+        F: FnMut(&str) -> Option<L::Layer>,
+    {
+        let SpatialSerialize {
+            entries,
+            size,
+            stack_order,
+            footprints,
+        } = SpatialSerialize::<String>::deserialize(d)?;
+        let mut location_component = ComponentTable::default();
+        let mut migrations = Vec::new();
+        for (entity, Location { coord, layer }) in entries.into_component_table().iter() {
+            let layer = match layer {
+                None => None,
+                Some(name) => match L::layer_from_name(name) {
+                    Some(layer) => Some(layer),
+                    None => match migrate(name) {
+                        Some(layer) => {
+                            migrations.push(Migration::Mapped {
+                                entity,
+                                from: name.clone(),
+                                to: layer,
+                            });
+                            Some(layer)
+                        }
+                        None => {
+                            migrations.push(Migration::Dropped {
+                                entity,
+                                from: name.clone(),
+                            });
+                            continue;
+                        }
+                    },
+                },
+            };
+            location_component.insert(
+                entity,
+                Location {
+                    coord: *coord,
+                    layer,
+                },
+            );
+        }
+        Self::from_location_component(size, location_component, &stack_order, footprints)
+            .map(|spatial_table| (spatial_table, migrations))
+            .map_err(validation_error)
+    }
+}
+
+impl<L: Layers> SpatialTable<L, Chunked<L>> {
+    /// Creates a spatial table with no bounds. Entities may be placed at any coord, including
+    /// coords with negative components.
+    pub fn new_chunked() -> Self {
+        Self::with_storage(Chunked::default())
+    }
+    /// The number of chunks currently allocated. Chunks are freed as soon as they become empty.
+    pub fn num_chunks(&self) -> usize {
+        self.spatial_grid.num_chunks()
+    }
+}
+
+impl<L: Layers, S: Storage<L>> SpatialTable<L, S> {
+    fn with_storage(spatial_grid: S) -> Self {
         Self {
-            location_component,
+            location_component: ComponentTable::default(),
             spatial_grid,
+            stacks: HashMap::new(),
+            layer_indices: L::LAYERS.iter().map(|_| LayerIndex::default()).collect(),
+            journal: None,
+            observers: Observers::default(),
+            history: None,
+            occupancy: None,
+            footprints: ComponentTable::default(),
         }
     }
     pub fn clear(&mut self) {
+        if self.is_journal_enabled() {
+            let coords = self
+                .location_component
+                .iter()
+                .flat_map(|(entity, location)| self.covered_coords(entity, location.coord))
+                .collect();
+            self.record_event(Event::Cleared { coords });
+        }
+        for (entity, &location) in self.location_component.iter() {
+            self.observers.notify(entity, Some(location), None);
+            let footprint = self.footprints.get(entity);
+            if footprint.is_some() {
+                self.observers.notify_footprint(entity, footprint, None);
+            }
+            if let Some(history) = self.history.as_mut() {
+                history.record((entity, Some(location), None));
+                if let Some(footprint) = footprint {
+                    history.record_footprint((entity, Some(footprint.clone()), None));
+                }
+            }
+        }
         self.location_component.clear();
-        for cell in self.spatial_grid.iter_mut() {
-            *cell = Default::default();
+        self.footprints.clear();
+        self.spatial_grid.clear();
+        self.stacks.clear();
+        self.clear_occupancy();
+        for layer_index in self.layer_indices.iter_mut() {
+            layer_index.clear();
         }
     }
-    pub fn enumerate(&self) -> Enumerate<L> {
-        self.spatial_grid.enumerate()
+    pub fn layers_at(&self, coord: Coord) -> Option<LayersAt<'_, L>> {
+        self.spatial_grid.get(coord).map(|layers| LayersAt {
+            layers,
+            stacks: &self.stacks,
+            coord,
+        })
     }
-    pub fn grid_size(&self) -> Size {
-        self.spatial_grid.size()
+    /// The entity on a layer at a coord. For stack layers this is the top of the stack.
+    pub fn entity_at(&self, coord: Coord, layer: L::Layer) -> Option<Entity> {
+        self.layers_at(coord)
+            .and_then(|layers| *layers.select_field(layer))
     }
-    pub fn layers_at(&self, coord: Coord) -> Option<&L> {
-        self.spatial_grid.get(coord)
+    pub fn is_occupied(&self, coord: Coord, layer: L::Layer) -> bool {
+        self.entity_at(coord, layer).is_some()
     }
-    pub fn layers_at_checked(&self, coord: Coord) -> &L {
-        self.spatial_grid.get_checked(coord)
+    /// Returns `true` iff any of `layers` is occupied at a coord. A single layer may be passed
+    /// in place of a [`LayerSet`].
+    pub fn is_occupied_in<M: Into<LayerSet<L>>>(&self, coord: Coord, layers: M) -> bool {
+        let layers = layers.into();
+        self.layers_at(coord).is_some_and(|cell| {
+            layers
+                .iter()
+                .any(|layer| cell.select_field(layer).is_some())
+        })
+    }
+    /// Iterates over the entities on a stack layer at a coord, from the bottom of the stack to
+    /// the top. Yields nothing for layers which aren't stack layers. Equivalent to calling
+    /// [`LayersAt::stack`] on the result of [`Self::layers_at`].
+    pub fn stack_at(&self, coord: Coord, layer: L::Layer) -> Stack<'_> {
+        stack(&self.stacks, coord, layer)
     }
     pub fn location_of(&self, entity: Entity) -> Option<&Location<L::Layer>> {
         self.location_component.get(entity)
@@ -235,44 +775,59 @@ impl<L: Layers> SpatialTable<L> {
         entity: Entity,
         location: Location<L::Layer>,
     ) -> Result<(), UpdateError> {
+        if self.location_component.get(entity) == Some(&location) {
+            // re-inserting the entity would move it to the top of its stack
+            return Ok(());
+        }
+        if self.footprints.contains(entity) {
+            // the new footprint may overlap the current one, so the entity must be removed
+            // from the spatial grid before it can be placed
+            return self
+                .relocate(&[(entity, Some(location))])
+                .map_err(|errors| errors[0].1);
+        }
         if let Some(layer) = location.layer {
-            let cell = self
-                .spatial_grid
-                .get_mut(location.coord)
-                .ok_or(UpdateError::DestinationOutOfBounds)?;
-            insert_layer(cell, entity, layer)?;
-        }
-        if let Some(original_location) = self.location_component.insert(entity, location) {
-            let original_cell = self.spatial_grid.get_checked_mut(original_location.coord);
-            if let Some(original_layer) = original_location.layer {
-                let should_match_entity = clear_layer(original_cell, original_layer);
-                debug_assert_eq!(
-                    should_match_entity,
-                    Some(entity),
-                    "Current location of entity doesn't contain entity in spatial grid"
-                );
-            }
+            self.insert_entity(location.coord, entity, layer)?;
+        }
+        let original_location = self.location_component.insert(entity, location);
+        if let Some(Location {
+            coord: original_coord,
+            layer: Some(original_layer),
+        }) = original_location
+        {
+            let removed = self.remove_entity(original_coord, entity, original_layer);
+            debug_assert!(
+                removed,
+                "Current location of entity doesn't contain entity in spatial grid"
+            );
         }
+        self.location_changed(entity, original_location, Some(location));
         Ok(())
     }
     pub fn update_coord(&mut self, entity: Entity, coord: Coord) -> Result<(), UpdateError> {
-        if let Some(location) = self.location_component.get_mut(entity) {
-            if coord != location.coord {
-                if let Some(layer) = location.layer {
-                    let cell = self
-                        .spatial_grid
-                        .get_mut(coord)
-                        .ok_or(UpdateError::DestinationOutOfBounds)?;
-                    insert_layer(cell, entity, layer)?;
-                    let original_cell = self.spatial_grid.get_checked_mut(location.coord);
-                    let should_match_entity = clear_layer(original_cell, layer);
-                    debug_assert_eq!(
-                        should_match_entity,
-                        Some(entity),
+        if let Some(&original_location) = self.location_component.get(entity) {
+            if coord != original_location.coord && self.footprints.contains(entity) {
+                let location = Location {
+                    coord,
+                    ..original_location
+                };
+                return self.update(entity, location);
+            }
+            if coord != original_location.coord {
+                if let Some(layer) = original_location.layer {
+                    self.insert_entity(coord, entity, layer)?;
+                    let removed = self.remove_entity(original_location.coord, entity, layer);
+                    debug_assert!(
+                        removed,
                         "Current location of entity doesn't contain entity in spatial grid"
                     );
                 }
-                location.coord = coord;
+                let location = Location {
+                    coord,
+                    ..original_location
+                };
+                self.location_component.insert(entity, location);
+                self.location_changed(entity, Some(original_location), Some(location));
             }
             Ok(())
         } else {
@@ -284,24 +839,26 @@ impl<L: Layers> SpatialTable<L> {
         entity: Entity,
         layer: L::Layer,
     ) -> Result<(), UpdateLayerError> {
-        if let Some(location) = self.location_component.get_mut(entity) {
-            if Some(layer) != location.layer {
-                debug_assert!(
-                    location.coord.is_valid(self.spatial_grid.size()),
-                    "Current location is outside the bounds of spatial grid"
-                );
-                let cell = self.spatial_grid.get_mut(location.coord).unwrap();
-                let dest_entity_slot = cell.select_field_mut(layer);
-                if let Some(dest_entity) = dest_entity_slot {
-                    return Err(UpdateLayerError::OccupiedBy(*dest_entity));
-                }
-                *dest_entity_slot = Some(entity);
-                if let Some(current_layer) = location.layer {
-                    let source_entity_slot = cell.select_field_mut(current_layer);
-                    debug_assert_eq!(*source_entity_slot, Some(entity));
-                    *source_entity_slot = None;
+        if let Some(&original_location) = self.location_component.get(entity) {
+            if Some(layer) != original_location.layer {
+                let coord = original_location.coord;
+                self.insert_entity(coord, entity, layer)
+                    .map_err(|error| match error {
+                        UpdateError::OccupiedBy(entity) => UpdateLayerError::OccupiedBy(entity),
+                        UpdateError::DestinationOutOfBounds => {
+                            UpdateLayerError::DestinationOutOfBounds
+                        }
+                    })?;
+                if let Some(current_layer) = original_location.layer {
+                    let removed = self.remove_entity(coord, entity, current_layer);
+                    debug_assert!(removed);
                 }
-                location.layer = Some(layer);
+                let location = Location {
+                    layer: Some(layer),
+                    ..original_location
+                };
+                self.location_component.insert(entity, location);
+                self.location_changed(entity, Some(original_location), Some(location));
             }
             Ok(())
         } else {
@@ -309,17 +866,16 @@ impl<L: Layers> SpatialTable<L> {
         }
     }
     pub fn clear_layer(&mut self, entity: Entity) -> Result<(), EntityHasNoCoord> {
-        if let Some(location) = self.location_component.get_mut(entity) {
-            if let Some(layer) = location.layer {
-                debug_assert!(
-                    location.coord.is_valid(self.spatial_grid.size()),
-                    "Current location is outside the bounds of spatial grid"
-                );
-                let cell = self.spatial_grid.get_mut(location.coord).unwrap();
-                let source_entity_slot = cell.select_field_mut(layer);
-                debug_assert_eq!(*source_entity_slot, Some(entity));
-                *source_entity_slot = None;
-                location.layer = None;
+        if let Some(&original_location) = self.location_component.get(entity) {
+            if let Some(layer) = original_location.layer {
+                let removed = self.remove_entity(original_location.coord, entity, layer);
+                debug_assert!(removed);
+                let location = Location {
+                    layer: None,
+                    ..original_location
+                };
+                self.location_component.insert(entity, location);
+                self.location_changed(entity, Some(original_location), Some(location));
             }
             Ok(())
         } else {
@@ -329,32 +885,456 @@ impl<L: Layers> SpatialTable<L> {
     pub fn remove(&mut self, entity: Entity) {
         if let Some(location) = self.location_component.remove(entity) {
             if let Some(layer) = location.layer {
-                clear_layer(self.spatial_grid.get_checked_mut(location.coord), layer);
+                self.remove_entity(location.coord, entity, layer);
             }
+            self.location_changed(entity, Some(location), None);
         }
     }
-    #[cfg(feature = "serialize")]
-    fn to_serialize(&self) -> SpatialSerialize<L::Layer> {
-        SpatialSerialize {
-            entries: self.location_component.entries().clone(),
-            size: self.spatial_grid.size(),
+    /// Exchanges the locations of two entities.
+    pub fn swap(&mut self, entity_a: Entity, entity_b: Entity) -> Result<(), SwapError> {
+        let location_a = *self
+            .location_of(entity_a)
+            .ok_or(SwapError::EntityHasNoCoord(entity_a))?;
+        let location_b = *self
+            .location_of(entity_b)
+            .ok_or(SwapError::EntityHasNoCoord(entity_b))?;
+        if entity_a != entity_b {
+            self.relocate(&[(entity_a, Some(location_b)), (entity_b, Some(location_a))])
+                .map_err(|errors| match errors[0].1 {
+                    UpdateError::OccupiedBy(occupant) => SwapError::OccupiedBy(occupant),
+                    UpdateError::DestinationOutOfBounds => SwapError::DestinationOutOfBounds,
+                })?;
         }
+        Ok(())
     }
-    #[cfg(feature = "serialize")]
-    fn from_serialize(SpatialSerialize { entries, size }: SpatialSerialize<L::Layer>) -> Self {
-        let location_component = entries.into_component_table();
-        let mut spatial_grid: Grid<L> = Grid::new_default(size);
-        for (entity, location) in location_component.iter() {
-            if let Some(layer) = location.layer {
-                let cell = spatial_grid.get_checked_mut(location.coord);
-                let slot = cell.select_field_mut(layer);
-                assert!(slot.is_none());
-                *slot = Some(entity);
+    /// Exchanges the layers of two entities, leaving each entity at its current coord. When the
+    /// entities are at different coords, the destination layer of either entity may already be
+    /// occupied by a third entity, in which case neither entity is changed.
+    pub fn swap_layers(
+        &mut self,
+        entity_a: Entity,
+        entity_b: Entity,
+    ) -> Result<(), SwapLayersError> {
+        let location_a = *self
+            .location_of(entity_a)
+            .ok_or(SwapLayersError::EntityHasNoCoord(entity_a))?;
+        let location_b = *self
+            .location_of(entity_b)
+            .ok_or(SwapLayersError::EntityHasNoCoord(entity_b))?;
+        if entity_a != entity_b && location_a.layer != location_b.layer {
+            // check both destinations first so that a failure leaves both entities unchanged
+            self.check_swap_layer(entity_a, location_a.coord, location_b.layer, entity_b)?;
+            self.check_swap_layer(entity_b, location_b.coord, location_a.layer, entity_a)?;
+            // entity_a leaves its layer first so entity_b can take its place
+            let result = self.clear_layer(entity_a);
+            debug_assert!(result.is_ok());
+            self.set_layer(entity_b, location_a.layer);
+            self.set_layer(entity_a, location_b.layer);
+        }
+        Ok(())
+    }
+    /// Checks that an entity anchored at `coord` could be moved onto `layer`, given that `other`
+    /// is moving off it.
+    fn check_swap_layer(
+        &self,
+        entity: Entity,
+        coord: Coord,
+        layer: Option<L::Layer>,
+        other: Entity,
+    ) -> Result<(), SwapLayersError> {
+        let layer = match layer {
+            Some(layer) => layer,
+            None => return Ok(()),
+        };
+        let covered = self.covered_coords(entity, coord);
+        for covered_coord in covered {
+            let cell = self
+                .spatial_grid
+                .get(covered_coord)
+                .ok_or(SwapLayersError::DestinationOutOfBounds)?;
+            match *cell.select_field(layer) {
+                Some(occupant) if occupant != other && !L::is_stack(layer) => {
+                    return Err(SwapLayersError::OccupiedBy(occupant))
+                }
+                _ => (),
             }
         }
-        Self {
-            location_component,
-            spatial_grid,
+        Ok(())
+    }
+    /// Calls [`Self::update_layer`] or [`Self::clear_layer`], for destinations which are known
+    /// to be free.
+    fn set_layer(&mut self, entity: Entity, layer: Option<L::Layer>) {
+        match layer {
+            Some(layer) => {
+                let result = self.update_layer(entity, layer);
+                debug_assert!(result.is_ok(), "Destination layer is occupied");
+            }
+            None => {
+                let result = self.clear_layer(entity);
+                debug_assert!(result.is_ok());
+            }
+        }
+    }
+    /// Like [`Self::update_coord`], but if the destination is occupied on the entity's layer, the
+    /// occupant is displaced according to `displace` rather than the update failing. On success,
+    /// returns a description of what happened to the occupant, if any. If the occupant can't be
+    /// displaced, neither entity is changed. For an entity with a footprint, only the first
+    /// occupant of the cells it would cover, in row-major order, is displaced, so the update
+    /// fails if the footprint would overlap any other entity.
+    pub fn update_coord_displacing(
+        &mut self,
+        entity: Entity,
+        coord: Coord,
+        displace: Displace,
+    ) -> Result<Displacement, UpdateError> {
+        let location = match self.location_component.get(entity) {
+            Some(&location) => location,
+            None => {
+                return self
+                    .update_coord(entity, coord)
+                    .map(|()| Displacement::None)
+            }
+        };
+        let layer = match location.layer {
+            Some(layer) if coord != location.coord && !L::is_stack(layer) => layer,
+            _ => {
+                return self
+                    .update_coord(entity, coord)
+                    .map(|()| Displacement::None)
+            }
+        };
+        let covered = self.covered_coords(entity, coord);
+        let mut occupant = None;
+        for covered_coord in covered {
+            let cell = self
+                .spatial_grid
+                .get(covered_coord)
+                .ok_or(UpdateError::DestinationOutOfBounds)?;
+            // the entity may already cover part of its destination
+            match *cell.select_field(layer) {
+                Some(other) if other != entity && occupant.is_none() => occupant = Some(other),
+                _ => (),
+            }
+        }
+        let occupant = match occupant {
+            Some(occupant) => occupant,
+            None => {
+                return self
+                    .update_coord(entity, coord)
+                    .map(|()| Displacement::None)
+            }
+        };
+        let occupant_coord = self
+            .location_component
+            .get(occupant)
+            .map_or(coord, |location| location.coord);
+        let (occupant_location, displacement) = match displace {
+            Displace::Fail => return Err(UpdateError::OccupiedBy(occupant)),
+            Displace::Swap => (location, Displacement::Swapped(occupant)),
+            Displace::Push(offset) => {
+                let occupant_coord = occupant_coord + offset;
+                (
+                    (occupant_coord, layer).into(),
+                    Displacement::Pushed {
+                        entity: occupant,
+                        coord: occupant_coord,
+                    },
+                )
+            }
+            Displace::Evict => (
+                Location {
+                    coord: occupant_coord,
+                    layer: None,
+                },
+                Displacement::Evicted(occupant),
+            ),
+        };
+        self.relocate(&[
+            (entity, Some((coord, layer).into())),
+            (occupant, Some(occupant_location)),
+        ])
+        .map_err(|errors| errors[0].1)?;
+        Ok(displacement)
+    }
+    /// Moves a collection of distinct entities as a single step. Each entity is removed from the
+    /// spatial grid before any entity is placed at its new location, so entities may move into
+    /// locations vacated by other entities in the same collection. A new location of `None`
+    /// removes the entity from the table. If any entity can't be placed, every entity is returned
+    /// to its original location, and an error is returned for each entity that couldn't be
+    /// placed.
+    fn relocate(
+        &mut self,
+        moves: &[(Entity, Option<Location<L::Layer>>)],
+    ) -> Result<(), Vec<(Entity, UpdateError)>> {
+        let errors = self.place_all(moves, true, &[]);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+    /// Returns the errors that [`Self::relocate`] would encounter for a collection of moves,
+    /// without changing the table.
+    fn check_relocate(
+        &mut self,
+        moves: &[(Entity, Option<Location<L::Layer>>)],
+    ) -> Vec<(Entity, UpdateError)> {
+        self.place_all(moves, false, &[])
+    }
+    /// Like [`Self::relocate`] if `commit` is `true`, and otherwise like
+    /// [`Self::check_relocate`]. Each entity in `footprints` is given its new footprint after
+    /// every entity is removed from the spatial grid, so entities in `footprints` should also
+    /// appear in `moves`.
+    fn place_all(
+        &mut self,
+        moves: &[(Entity, Option<Location<L::Layer>>)],
+        commit: bool,
+        footprints: &[(Entity, Footprint)],
+    ) -> Vec<(Entity, UpdateError)> {
+        // an entity that stays where it is keeps its position in any stack it belongs to
+        let moves = moves
+            .iter()
+            .filter(|&&(entity, location)| {
+                self.location_component.get(entity).copied() != location
+                    || footprints.iter().any(|&(e, _)| e == entity)
+            })
+            .copied()
+            .collect::<Vec<_>>();
+        let moves = moves.as_slice();
+        let original_locations = moves
+            .iter()
+            .map(|&(entity, _)| self.location_component.get(entity).copied())
+            .collect::<Vec<_>>();
+        let original_stacks = self.stacks_of(&original_locations, moves);
+        for (&(entity, _), original_location) in moves.iter().zip(original_locations.iter()) {
+            if let Some(Location {
+                coord,
+                layer: Some(layer),
+            }) = *original_location
+            {
+                let removed = self.remove_entity(coord, entity, layer);
+                debug_assert!(
+                    removed,
+                    "Current location of entity doesn't contain entity in spatial grid"
+                );
+            }
+        }
+        let original_footprints = footprints
+            .iter()
+            .map(|(entity, footprint)| {
+                let original_footprint = self.footprints.remove(*entity);
+                if !footprint.is_single() {
+                    self.footprints.insert(*entity, footprint.clone());
+                }
+                (*entity, original_footprint)
+            })
+            .collect::<Vec<_>>();
+        let mut errors = Vec::new();
+        let mut placed = Vec::new();
+        for &(entity, location) in moves {
+            if let Some(Location {
+                coord,
+                layer: Some(layer),
+            }) = location
+            {
+                match self.insert_entity(coord, entity, layer) {
+                    Ok(()) => placed.push((entity, coord, layer)),
+                    Err(error) => errors.push((entity, error)),
+                }
+            }
+        }
+        if !commit || !errors.is_empty() {
+            for (entity, coord, layer) in placed {
+                self.remove_entity(coord, entity, layer);
+            }
+            for (entity, original_footprint) in original_footprints.into_iter().rev() {
+                self.footprints.remove(entity);
+                if let Some(original_footprint) = original_footprint {
+                    self.footprints.insert(entity, original_footprint);
+                }
+            }
+            for (&(entity, _), original_location) in moves.iter().zip(original_locations) {
+                if let Some(Location {
+                    coord,
+                    layer: Some(layer),
+                }) = original_location
+                {
+                    let result = self.insert_entity(coord, entity, layer);
+                    debug_assert!(result.is_ok(), "Failed to restore original location");
+                }
+            }
+            // re-inserting pushes entities onto the top of their stacks
+            for ((coord, layer), stack) in original_stacks {
+                if let Some(cell) = self.spatial_grid.get_mut(coord) {
+                    *cell.select_field_mut(layer) = stack.last().copied();
+                }
+                self.stacks.insert((coord, layer), stack);
+            }
+            return errors;
+        }
+        for (&(entity, location), original_location) in moves.iter().zip(original_locations) {
+            if let Some(location) = location {
+                self.location_component.insert(entity, location);
+            } else {
+                self.location_component.remove(entity);
+            }
+            let original_footprint = match original_footprints.iter().find(|&&(e, _)| e == entity) {
+                Some((_, original_footprint)) => original_footprint.clone(),
+                None => self.footprints.get(entity).cloned(),
+            };
+            self.changed(entity, original_location, location, original_footprint);
+        }
+        errors
+    }
+    /// Returns a copy of each stack containing one of the moved entities at its original
+    /// location.
+    fn stacks_of(
+        &self,
+        original_locations: &[Option<Location<L::Layer>>],
+        moves: &[(Entity, Option<Location<L::Layer>>)],
+    ) -> Stacks<L::Layer> {
+        let mut stacks = Stacks::new();
+        for (&(entity, _), original_location) in moves.iter().zip(original_locations) {
+            if let Some(Location {
+                coord,
+                layer: Some(layer),
+            }) = *original_location
+            {
+                if !L::is_stack(layer) {
+                    continue;
+                }
+                let coords = self.covered_coords(entity, coord);
+                for cell_coord in coords {
+                    let key = (cell_coord, layer);
+                    if let Some(stack) = self.stacks.get(&key) {
+                        stacks.entry(key).or_insert_with(|| stack.clone());
+                    }
+                }
+            }
+        }
+        stacks
+    }
+    /// Places an entity in the slot for `layer` in every cell covered by its footprint when
+    /// anchored at `coord`. If any cell can't be occupied, no cells are changed. All changes to
+    /// the contents of cells go through this method and [`Self::remove_entity`].
+    fn insert_entity(
+        &mut self,
+        coord: Coord,
+        entity: Entity,
+        layer: L::Layer,
+    ) -> Result<(), UpdateError> {
+        let footprint = match self.footprints.get(entity) {
+            Some(footprint) => footprint.clone(),
+            None => return self.insert_cell(coord, entity, layer),
+        };
+        for (i, cell_coord) in footprint.coords(coord).enumerate() {
+            if let Err(error) = self.insert_cell(cell_coord, entity, layer) {
+                for cell_coord in footprint.coords(coord).take(i) {
+                    self.remove_cell(cell_coord, entity, layer);
+                }
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+    /// Removes an entity from the slot for `layer` in every cell covered by its footprint when
+    /// anchored at `coord`. Returns `true` iff the entity was present in every cell.
+    fn remove_entity(&mut self, coord: Coord, entity: Entity, layer: L::Layer) -> bool {
+        let footprint = match self.footprints.get(entity) {
+            Some(footprint) => footprint.clone(),
+            None => return self.remove_cell(coord, entity, layer),
+        };
+        let mut removed = true;
+        for cell_coord in footprint.coords(coord) {
+            removed &= self.remove_cell(cell_coord, entity, layer);
+        }
+        removed
+    }
+    fn insert_cell(
+        &mut self,
+        coord: Coord,
+        entity: Entity,
+        layer: L::Layer,
+    ) -> Result<(), UpdateError> {
+        let cell = self
+            .spatial_grid
+            .get_mut_or_alloc(coord)
+            .ok_or(UpdateError::DestinationOutOfBounds)?;
+        insert_layer(cell, &mut self.stacks, coord, entity, layer)?;
+        self.spatial_grid.occupy(coord);
+        self.update_occupancy(coord, layer);
+        Ok(())
+    }
+    /// Returns `true` iff the entity was present
+    fn remove_cell(&mut self, coord: Coord, entity: Entity, layer: L::Layer) -> bool {
+        let removed = match self.spatial_grid.get_mut(coord) {
+            Some(cell) => remove_layer(cell, &mut self.stacks, coord, entity, layer),
+            None => false,
+        };
+        if removed {
+            self.spatial_grid.vacate(coord);
+            self.update_occupancy(coord, layer);
+        }
+        removed
+    }
+    /// Called after the location of an entity changes
+    fn location_changed(
+        &mut self,
+        entity: Entity,
+        original_location: Option<Location<L::Layer>>,
+        location: Option<Location<L::Layer>>,
+    ) {
+        let footprint = self.footprints.get(entity).cloned();
+        self.changed(entity, original_location, location, footprint);
+    }
+    /// Updates the layer indices and reports a change to the location or footprint of an
+    /// entity to the journal, observers and history. An entity's footprint is discarded when
+    /// it's removed from the table. Does nothing if neither changed.
+    fn changed(
+        &mut self,
+        entity: Entity,
+        original_location: Option<Location<L::Layer>>,
+        location: Option<Location<L::Layer>>,
+        original_footprint: Option<Footprint>,
+    ) {
+        let original_layer = original_location.and_then(|l| l.layer);
+        let layer = location.and_then(|l| l.layer);
+        if original_layer != layer {
+            if let Some(original_layer) = original_layer {
+                self.layer_indices[L::layer_index(original_layer)].remove(entity);
+            }
+            if let Some(layer) = layer {
+                self.layer_indices[L::layer_index(layer)].insert(entity);
+            }
+        }
+        if original_location.is_some() && location.is_none() {
+            self.footprints.remove(entity);
+        }
+        let footprint = self.footprints.get(entity).cloned();
+        if let Some(event) = Event::new(
+            entity,
+            original_location,
+            location,
+            original_footprint.as_ref(),
+            footprint.as_ref(),
+        ) {
+            self.record_event(event);
+        }
+        if original_location != location {
+            self.observers.notify(entity, original_location, location);
+            if let Some(history) = self.history.as_mut() {
+                history.record((entity, original_location, location));
+            }
+        }
+        if original_footprint != footprint {
+            self.observers.notify_footprint(
+                entity,
+                original_footprint.as_ref(),
+                footprint.as_ref(),
+            );
+            if let Some(history) = self.history.as_mut() {
+                history.record_footprint((entity, original_footprint, footprint));
+            }
         }
     }
 }
@@ -383,12 +1363,127 @@ impl UpdateError {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum UpdateLayerError {
+pub enum UpdateLayerError {
+    OccupiedBy(Entity),
+    EntityHasNoCoord,
+    /// The entity has no layer and its footprint covers a cell outside the spatial grid
+    DestinationOutOfBounds,
+}
+
+impl UpdateLayerError {
+    pub fn unwrap_occupied_by(self) -> Entity {
+        match self {
+            Self::OccupiedBy(entity) => entity,
+            _ => panic!("unexpected {:?} (expected OccupiedBy(_))", self),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntityHasNoCoord;
+
+/// A change made to an entity by [`SpatialTable::deserialize_with_migration`] because its layer
+/// wasn't recognised
+#[cfg(feature = "serialize")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Migration<L> {
+    /// The entity was moved from the unrecognised layer to a new layer
+    Mapped { entity: Entity, from: String, to: L },
+    /// The entity was left out of the table
+    Dropped { entity: Entity, from: String },
+}
+
+/// Every change made by [`SpatialTable::deserialize_with_migration`]
+#[cfg(feature = "serialize")]
+pub type MigrationReport<L> = Vec<Migration<L>>;
+
+/// A problem with serialized data which prevents it from being loaded as a [`SpatialTable`]
+#[cfg(feature = "serialize")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The entity's coord is outside the bounds of the spatial grid
+    OutOfBounds { entity: Entity, coord: Coord },
+    /// The entity's layer at its coord is already occupied by another entity
+    OccupiedBy {
+        entity: Entity,
+        occupant: Entity,
+        coord: Coord,
+    },
+    /// The entity has a footprint but no location
+    FootprintWithoutLocation { entity: Entity },
+}
+
+#[cfg(feature = "serialize")]
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::OutOfBounds { entity, coord } => write!(
+                f,
+                "entity {:?} is at {:?} which is outside the spatial grid",
+                entity, coord
+            ),
+            Self::OccupiedBy {
+                entity,
+                occupant,
+                coord,
+            } => write!(
+                f,
+                "entity {:?} is on the same layer at {:?} as entity {:?}",
+                entity, coord, occupant
+            ),
+            Self::FootprintWithoutLocation { entity } => {
+                write!(f, "entity {:?} has a footprint but no location", entity)
+            }
+        }
+    }
+}
+
+/// What to do with the occupant of a destination when moving an entity with
+/// [`SpatialTable::update_coord_displacing`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Displace {
+    /// Leave the occupant where it is and fail with [`UpdateError::OccupiedBy`]
+    Fail,
+    /// Move the occupant to the original location of the moving entity
+    Swap,
+    /// Move the occupant by the given offset, keeping it on the same layer
+    Push(Coord),
+    /// Leave the occupant at its coord, but remove it from its layer
+    Evict,
+}
+
+/// What happened to the occupant of a destination when moving an entity with
+/// [`SpatialTable::update_coord_displacing`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Displacement {
+    /// The destination was not occupied
+    None,
+    Swapped(Entity),
+    Pushed {
+        entity: Entity,
+        coord: Coord,
+    },
+    Evicted(Entity),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapError {
+    EntityHasNoCoord(Entity),
+    /// Only possible when either entity covers more than one cell
+    OccupiedBy(Entity),
+    /// Only possible when either entity covers more than one cell
+    DestinationOutOfBounds,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapLayersError {
     OccupiedBy(Entity),
-    EntityHasNoCoord,
+    EntityHasNoCoord(Entity),
+    /// One of the entities has no layer and its footprint covers a cell outside the spatial grid
+    DestinationOutOfBounds,
 }
 
-impl UpdateLayerError {
+impl SwapLayersError {
     pub fn unwrap_occupied_by(self) -> Entity {
         match self {
             Self::OccupiedBy(entity) => entity,
@@ -397,24 +1492,68 @@ impl UpdateLayerError {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct EntityHasNoCoord;
+/// Entities occupying each non-empty stack layer slot, from the bottom of the stack to the top.
+/// The slot itself always holds the top of its stack.
+type Stacks<T> = HashMap<(Coord, T), Vec<Entity>>;
+
+fn stack<T: Eq + Hash>(stacks: &Stacks<T>, coord: Coord, layer: T) -> Stack<'_> {
+    stacks
+        .get(&(coord, layer))
+        .map(|stack| stack.as_slice())
+        .unwrap_or(&[])
+        .iter()
+        .copied()
+}
 
 fn insert_layer<L: Layers>(
     layers: &mut L,
+    stacks: &mut Stacks<L::Layer>,
+    coord: Coord,
     entity: Entity,
     layer: L::Layer,
 ) -> Result<(), OccupiedBy> {
     let layer_field = layers.select_field_mut(layer);
-    if let Some(&occupant) = layer_field.as_ref() {
+    if L::is_stack(layer) {
+        stacks.entry((coord, layer)).or_default().push(entity);
+        *layer_field = Some(entity);
+        Ok(())
+    } else if let Some(&occupant) = layer_field.as_ref() {
         Err(OccupiedBy(occupant))
     } else {
         *layer_field = Some(entity);
         Ok(())
     }
 }
-fn clear_layer<L: Layers>(layers: &mut L, layer: L::Layer) -> Option<Entity> {
-    layers.select_field_mut(layer).take()
+/// Returns `true` iff the entity was present on the given layer.
+fn remove_layer<L: Layers>(
+    layers: &mut L,
+    stacks: &mut Stacks<L::Layer>,
+    coord: Coord,
+    entity: Entity,
+    layer: L::Layer,
+) -> bool {
+    let layer_field = layers.select_field_mut(layer);
+    if L::is_stack(layer) {
+        let stack = match stacks.get_mut(&(coord, layer)) {
+            Some(stack) => stack,
+            None => return false,
+        };
+        let index = match stack.iter().position(|&e| e == entity) {
+            Some(index) => index,
+            None => return false,
+        };
+        stack.remove(index);
+        *layer_field = stack.last().copied();
+        if stack.is_empty() {
+            stacks.remove(&(coord, layer));
+        }
+        true
+    } else if *layer_field == Some(entity) {
+        *layer_field = None;
+        true
+    } else {
+        false
+    }
 }
 
 #[cfg(feature = "serialize")]
@@ -422,6 +1561,13 @@ fn clear_layer<L: Layers>(layers: &mut L, layer: L::Layer) -> Option<Entity> {
 struct SpatialSerialize<L> {
     entries: ComponentTableEntries<Location<L>>,
     size: Size,
+    /// The entities on stack layers, from the bottom to the top of each stack. Stacks in data
+    /// saved without this field are rebuilt in the order of `entries`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    stack_order: Vec<Entity>,
+    /// Entities which cover more than one cell
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    footprints: Vec<(Entity, Footprint)>,
 }
 
 #[cfg(feature = "serialize")]
@@ -440,10 +1586,16 @@ where
     L::Layer: Deserialize<'a>,
 {
     fn deserialize<D: serde::Deserializer<'a>>(d: D) -> Result<Self, D::Error> {
-        Deserialize::deserialize(d).map(Self::from_serialize)
+        Self::from_serialize(Deserialize::deserialize(d)?).map_err(validation_error)
     }
 }
 
+#[cfg(feature = "serialize")]
+fn validation_error<E: serde::de::Error>(errors: Vec<ValidationError>) -> E {
+    let messages = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+    E::custom(messages.join("; "))
+}
+
 #[cfg(test)]
 mod test {
     declare_layers_module! {
@@ -454,7 +1606,10 @@ mod test {
     }
     use layers::{Layer, Layers};
     type SpatialTable = super::SpatialTable<Layers>;
-    use super::{Coord, Location, Size, UpdateError, UpdateLayerError};
+    use super::{
+        Coord, Displace, Displacement, Location, Size, SwapError, SwapLayersError, UpdateError,
+        UpdateLayerError,
+    };
     use entity_table::EntityAllocator;
 
     #[test]
@@ -650,4 +1805,708 @@ mod test {
         assert_eq!(spatial_table.coord_of(entity_b), Some(Coord::new(6, 7)));
         assert_eq!(spatial_table.layer_of(entity_b), None);
     }
+
+    #[test]
+    fn layer_metadata() {
+        assert_eq!(Layer::COUNT, 2);
+        assert_eq!(Layer::ALL, [Layer::Feature, Layer::Character]);
+        for (index, &layer) in Layer::ALL.iter().enumerate() {
+            assert_eq!(layer.index(), index);
+            assert_eq!(Layer::from_index(index), Some(layer));
+            assert_eq!(layer.name().parse(), Ok(layer));
+        }
+        assert_eq!(Layer::from_index(2), None);
+        assert_eq!(Layer::Character.name(), "Character");
+        assert_eq!(Layer::Feature.to_string(), "Feature");
+        assert_eq!(
+            "Floor".parse::<Layer>(),
+            Err(super::ParseLayerError("Floor".to_string())),
+        );
+    }
+
+    #[test]
+    fn entity_at() {
+        use super::Layers as _;
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let wall = entity_allocator.alloc();
+        let hero = entity_allocator.alloc();
+        let coord = Coord::new(3, 4);
+        spatial_table
+            .update(hero, (coord, Layer::Character).into())
+            .unwrap();
+        assert_eq!(spatial_table.entity_at(coord, Layer::Character), Some(hero));
+        assert_eq!(spatial_table.entity_at(coord, Layer::Feature), None);
+        assert!(spatial_table.is_occupied(coord, Layer::Character));
+        assert!(!spatial_table.is_occupied(coord, Layer::Feature));
+        assert!(!spatial_table.is_occupied(Coord::new(10, 0), Layer::Character));
+        spatial_table
+            .update(wall, (coord, Layer::Feature).into())
+            .unwrap();
+        assert_eq!(
+            spatial_table
+                .layers_at_checked(coord)
+                .occupied()
+                .collect::<Vec<_>>(),
+            vec![(Layer::Feature, wall), (Layer::Character, hero)],
+        );
+        assert_eq!(Layers::default().occupied().next(), None);
+    }
+
+    #[test]
+    fn swap() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let entity_a = entity_allocator.alloc();
+        let entity_b = entity_allocator.alloc();
+        let entity_c = entity_allocator.alloc();
+        let entity_d = entity_allocator.alloc();
+
+        spatial_table
+            .update(entity_a, (Coord::new(1, 1), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(entity_b, (Coord::new(1, 2), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(entity_c, (Coord::new(1, 2), Layer::Feature).into())
+            .unwrap();
+
+        spatial_table.swap(entity_a, entity_b).unwrap();
+        assert_eq!(spatial_table.coord_of(entity_a), Some(Coord::new(1, 2)));
+        assert_eq!(spatial_table.coord_of(entity_b), Some(Coord::new(1, 1)));
+        assert_eq!(
+            *spatial_table.layers_at_checked(Coord::new(1, 2)),
+            Layers {
+                feature: Some(entity_c),
+                character: Some(entity_a),
+            },
+        );
+        assert_eq!(
+            spatial_table.swap(entity_a, entity_d),
+            Err(SwapError::EntityHasNoCoord(entity_d)),
+        );
+
+        // swapping layers in the same cell
+        spatial_table.swap_layers(entity_a, entity_c).unwrap();
+        assert_eq!(
+            *spatial_table.layers_at_checked(Coord::new(1, 2)),
+            Layers {
+                feature: Some(entity_a),
+                character: Some(entity_c),
+            },
+        );
+
+        // entity_b would move onto the feature layer at (1, 1) which is occupied by entity_d
+        spatial_table.remove(entity_c);
+        spatial_table
+            .update(entity_d, (Coord::new(1, 1), Layer::Feature).into())
+            .unwrap();
+        assert_eq!(
+            spatial_table.swap_layers(entity_a, entity_b),
+            Err(SwapLayersError::OccupiedBy(entity_d)),
+        );
+        assert_eq!(spatial_table.layer_of(entity_a), Some(Layer::Feature));
+        assert_eq!(spatial_table.layer_of(entity_b), Some(Layer::Character));
+        assert_eq!(
+            *spatial_table.layers_at_checked(Coord::new(1, 1)),
+            Layers {
+                feature: Some(entity_d),
+                character: Some(entity_b),
+            },
+        );
+    }
+
+    #[test]
+    fn displace() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let mover = entity_allocator.alloc();
+        let occupant = entity_allocator.alloc();
+        let blocker = entity_allocator.alloc();
+
+        spatial_table
+            .update(mover, (Coord::new(1, 1), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(occupant, (Coord::new(2, 1), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(blocker, (Coord::new(3, 1), Layer::Character).into())
+            .unwrap();
+
+        assert_eq!(
+            spatial_table.update_coord_displacing(mover, Coord::new(2, 1), Displace::Fail),
+            Err(UpdateError::OccupiedBy(occupant)),
+        );
+
+        // pushing into another character fails without changing anything
+        assert_eq!(
+            spatial_table.update_coord_displacing(
+                mover,
+                Coord::new(2, 1),
+                Displace::Push(Coord::new(1, 0)),
+            ),
+            Err(UpdateError::OccupiedBy(blocker)),
+        );
+        assert_eq!(spatial_table.coord_of(mover), Some(Coord::new(1, 1)));
+        assert_eq!(spatial_table.coord_of(occupant), Some(Coord::new(2, 1)));
+
+        assert_eq!(
+            spatial_table.update_coord_displacing(
+                mover,
+                Coord::new(2, 1),
+                Displace::Push(Coord::new(0, 1)),
+            ),
+            Ok(Displacement::Pushed {
+                entity: occupant,
+                coord: Coord::new(2, 2),
+            }),
+        );
+        assert_eq!(spatial_table.coord_of(mover), Some(Coord::new(2, 1)));
+        assert_eq!(spatial_table.coord_of(occupant), Some(Coord::new(2, 2)));
+
+        assert_eq!(
+            spatial_table.update_coord_displacing(mover, Coord::new(2, 2), Displace::Swap),
+            Ok(Displacement::Swapped(occupant)),
+        );
+        assert_eq!(spatial_table.coord_of(mover), Some(Coord::new(2, 2)));
+        assert_eq!(spatial_table.coord_of(occupant), Some(Coord::new(2, 1)));
+
+        assert_eq!(
+            spatial_table.update_coord_displacing(mover, Coord::new(3, 1), Displace::Evict),
+            Ok(Displacement::Evicted(blocker)),
+        );
+        assert_eq!(
+            spatial_table.location_of(blocker).cloned(),
+            Some(Location {
+                coord: Coord::new(3, 1),
+                layer: None,
+            }),
+        );
+        assert_eq!(
+            spatial_table.layers_at_checked(Coord::new(3, 1)).character,
+            Some(mover),
+        );
+
+        assert_eq!(
+            spatial_table.update_coord_displacing(mover, Coord::new(4, 1), Displace::Fail),
+            Ok(Displacement::None),
+        );
+    }
+
+    #[test]
+    fn resize() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let entity_a = entity_allocator.alloc();
+        let entity_b = entity_allocator.alloc();
+        let entity_c = entity_allocator.alloc();
+        spatial_table
+            .update(entity_a, (Coord::new(1, 1), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(entity_b, (Coord::new(8, 8), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update_coord(entity_c, Coord::new(0, 9))
+            .unwrap();
+
+        // grow the map by 2 cells on every side
+        assert_eq!(
+            spatial_table.resize(Size::new(14, 14), Coord::new(2, 2)),
+            vec![]
+        );
+        assert_eq!(spatial_table.grid_size(), Size::new(14, 14));
+        assert_eq!(spatial_table.coord_of(entity_a), Some(Coord::new(3, 3)));
+        assert_eq!(spatial_table.coord_of(entity_c), Some(Coord::new(2, 11)));
+        assert_eq!(
+            spatial_table
+                .layers_at_checked(Coord::new(10, 10))
+                .character,
+            Some(entity_b),
+        );
+        assert_eq!(
+            spatial_table.layers_at_checked(Coord::new(8, 8)).character,
+            None,
+        );
+
+        // crop to the top-left 6x6 cells, leaving entity_c outside the grid as it has no layer
+        assert_eq!(
+            spatial_table.resize(Size::new(6, 6), Coord::new(-1, -1)),
+            vec![entity_b],
+        );
+        assert_eq!(spatial_table.location_of(entity_b), None);
+        assert_eq!(spatial_table.coord_of(entity_c), Some(Coord::new(1, 10)));
+        assert_eq!(spatial_table.coord_of(entity_a), Some(Coord::new(2, 2)));
+        assert_eq!(
+            spatial_table.layers_at_checked(Coord::new(2, 2)).character,
+            Some(entity_a),
+        );
+        spatial_table
+            .update_coord(entity_a, Coord::new(5, 5))
+            .unwrap();
+
+        // entities whose coords would overflow are removed
+        spatial_table
+            .update_coord(entity_c, Coord::new(i32::MAX, 0))
+            .unwrap();
+        assert_eq!(
+            spatial_table.resize(Size::new(7, 6), Coord::new(1, 0)),
+            vec![entity_c],
+        );
+        assert_eq!(spatial_table.location_of(entity_c), None);
+        assert_eq!(spatial_table.coord_of(entity_a), Some(Coord::new(6, 5)));
+    }
+
+    mod stack {
+        declare_layers_module! {
+            layers {
+                feature: Feature,
+                item: Item [stack],
+            }
+        }
+        use super::super::{Coord, Size, UpdateError};
+        use entity_table::EntityAllocator;
+        use layers::{Layer, Layers};
+        type SpatialTable = super::super::SpatialTable<Layers>;
+
+        #[test]
+        fn stack_layers() {
+            let mut entity_allocator = EntityAllocator::default();
+            let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+            let feature = entity_allocator.alloc();
+            let item_a = entity_allocator.alloc();
+            let item_b = entity_allocator.alloc();
+            let item_c = entity_allocator.alloc();
+            let coord = Coord::new(3, 4);
+
+            spatial_table
+                .update(feature, (coord, Layer::Feature).into())
+                .unwrap();
+            for &item in &[item_a, item_b, item_c] {
+                spatial_table
+                    .update(item, (coord, Layer::Item).into())
+                    .unwrap();
+            }
+            assert_eq!(
+                spatial_table
+                    .stack_at(coord, Layer::Item)
+                    .collect::<Vec<_>>(),
+                vec![item_a, item_b, item_c],
+            );
+            assert_eq!(spatial_table.layers_at_checked(coord).item, Some(item_c));
+            assert_eq!(spatial_table.stack_at(coord, Layer::Feature).count(), 0);
+
+            // moving an item out of the middle of the stack leaves the rest in order
+            spatial_table
+                .update_coord(item_b, Coord::new(5, 5))
+                .unwrap();
+            assert_eq!(
+                spatial_table
+                    .stack_at(coord, Layer::Item)
+                    .collect::<Vec<_>>(),
+                vec![item_a, item_c],
+            );
+            assert_eq!(
+                spatial_table
+                    .stack_at(Coord::new(5, 5), Layer::Item)
+                    .collect::<Vec<_>>(),
+                vec![item_b],
+            );
+
+            // removing the top of the stack exposes the entity beneath it
+            spatial_table.remove(item_c);
+            assert_eq!(spatial_table.layers_at_checked(coord).item, Some(item_a));
+
+            // single-occupancy layers are unaffected
+            assert_eq!(
+                spatial_table.update_layer(item_a, Layer::Feature),
+                Err(super::super::UpdateLayerError::OccupiedBy(feature)),
+            );
+            spatial_table.update_layer(feature, Layer::Item).unwrap();
+            assert_eq!(
+                spatial_table
+                    .stack_at(coord, Layer::Item)
+                    .collect::<Vec<_>>(),
+                vec![item_a, feature],
+            );
+            assert_eq!(
+                spatial_table.update_coord(item_a, Coord::new(10, 10)),
+                Err(UpdateError::DestinationOutOfBounds),
+            );
+
+            spatial_table.clear_layer(item_a).unwrap();
+            spatial_table.clear_layer(feature).unwrap();
+            assert_eq!(spatial_table.stack_at(coord, Layer::Item).count(), 0);
+            assert_eq!(
+                *spatial_table.layers_at_checked(coord),
+                Layers {
+                    feature: None,
+                    item: None,
+                },
+            );
+        }
+
+        #[test]
+        fn layers_at_stack() {
+            let mut entity_allocator = EntityAllocator::default();
+            let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+            let item_a = entity_allocator.alloc();
+            let item_b = entity_allocator.alloc();
+            let coord = Coord::new(3, 4);
+            for &item in &[item_a, item_b] {
+                spatial_table
+                    .update(item, (coord, Layer::Item).into())
+                    .unwrap();
+            }
+            let layers = spatial_table.layers_at(coord).unwrap();
+            assert_eq!(layers.item, Some(item_b));
+            assert_eq!(
+                layers.stack(Layer::Item).collect::<Vec<_>>(),
+                vec![item_a, item_b],
+            );
+            assert_eq!(layers.stack(Layer::Feature).count(), 0);
+        }
+
+        #[test]
+        fn failed_transaction_keeps_stack_order() {
+            let mut entity_allocator = EntityAllocator::default();
+            let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+            let items = [
+                entity_allocator.alloc(),
+                entity_allocator.alloc(),
+                entity_allocator.alloc(),
+            ];
+            let feature_a = entity_allocator.alloc();
+            let feature_b = entity_allocator.alloc();
+            let coord = Coord::new(3, 4);
+            for &item in &items {
+                spatial_table
+                    .update(item, (coord, Layer::Item).into())
+                    .unwrap();
+            }
+            spatial_table
+                .update(feature_a, (Coord::new(0, 0), Layer::Feature).into())
+                .unwrap();
+            spatial_table
+                .update(feature_b, (Coord::new(1, 0), Layer::Feature).into())
+                .unwrap();
+
+            let mut transaction = spatial_table.transaction();
+            transaction.update_coord(items[0], Coord::new(5, 5));
+            transaction.update_coord(feature_a, Coord::new(1, 0));
+            assert!(transaction.commit().is_err());
+            assert_eq!(
+                spatial_table
+                    .stack_at(coord, Layer::Item)
+                    .collect::<Vec<_>>(),
+                items,
+            );
+            assert_eq!(spatial_table.layers_at_checked(coord).item, Some(items[2]));
+        }
+
+        #[test]
+        fn update_to_same_location_keeps_stack_order() {
+            let mut entity_allocator = EntityAllocator::default();
+            let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+            let items = [
+                entity_allocator.alloc(),
+                entity_allocator.alloc(),
+                entity_allocator.alloc(),
+            ];
+            let coord = Coord::new(3, 4);
+            for &item in &items {
+                spatial_table
+                    .update(item, (coord, Layer::Item).into())
+                    .unwrap();
+            }
+            spatial_table
+                .update(items[0], (coord, Layer::Item).into())
+                .unwrap();
+            spatial_table.update_coord(items[1], coord).unwrap();
+            spatial_table.update_layer(items[1], Layer::Item).unwrap();
+            assert_eq!(
+                spatial_table
+                    .stack_at(coord, Layer::Item)
+                    .collect::<Vec<_>>(),
+                items,
+            );
+        }
+    }
+}
+
+#[cfg(all(test, feature = "serialize"))]
+mod serialize_test {
+    declare_layers_module! {
+        layers {
+            feature: Feature,
+            character: Character,
+            item: Item [stack],
+        }
+    }
+    use super::{Coord, Footprint, Migration, Size, ValidationError};
+    use entity_table::EntityAllocator;
+    use layers::{Layer, Layers};
+    type SpatialTable = super::SpatialTable<Layers>;
+
+    fn entry(index: u32, x: i32, y: i32, layer: &str) -> String {
+        format!(
+            r#"{{"data":{{"coord":{{"x":{},"y":{}}},"layer":"{}"}},"entity":{{"id":{},"index":{}}}}}"#,
+            x, y, layer, index, index
+        )
+    }
+
+    fn json(entries: &[String], size: u32) -> String {
+        format!(
+            r#"{{"entries":{{"vec":[{}]}},"size":{{"x":{},"y":{}}}}}"#,
+            entries.join(","),
+            size,
+            size
+        )
+    }
+
+    mod v2 {
+        declare_layers_module! {
+            layers {
+                character: Character,
+                floor: Floor,
+            }
+        }
+        pub use layers::{Layer, Layers};
+    }
+
+    #[test]
+    fn stack_order() {
+        #[derive(serde::Serialize, serde::Deserialize)]
+        struct Compact(#[serde(with = "crate::compact")] SpatialTable);
+
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(4, 4));
+        let a = entity_allocator.alloc();
+        let b = entity_allocator.alloc();
+        let c = entity_allocator.alloc();
+        for entity in [b, c, a] {
+            spatial_table
+                .update(entity, (Coord::new(1, 1), Layer::Item).into())
+                .unwrap();
+        }
+        let default = serde_json::to_string(&spatial_table).unwrap();
+        let from_default: SpatialTable = serde_json::from_str(&default).unwrap();
+        let compact = serde_json::to_string(&Compact(spatial_table)).unwrap();
+        let Compact(from_compact) = serde_json::from_str(&compact).unwrap();
+        for spatial_table in [from_default, from_compact] {
+            assert!(spatial_table
+                .stack_at(Coord::new(1, 1), Layer::Item)
+                .eq([b, c, a]));
+            assert_eq!(
+                spatial_table.layers_at_checked(Coord::new(1, 1)).item,
+                Some(a)
+            );
+        }
+
+        // data saved before the stack order was recorded is stacked in the order of its entries
+        let old = json(&[entry(1, 1, 1, "Item"), entry(0, 1, 1, "Item")], 4);
+        let spatial_table: SpatialTable = serde_json::from_str(&old).unwrap();
+        assert!(spatial_table
+            .stack_at(Coord::new(1, 1), Layer::Item)
+            .eq([b, a]));
+    }
+
+    #[test]
+    fn footprints() {
+        #[derive(serde::Serialize, serde::Deserialize)]
+        struct Compact(#[serde(with = "crate::compact")] SpatialTable);
+
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(6, 6));
+        let boss = entity_allocator.alloc();
+        let hero = entity_allocator.alloc();
+        let shape = Footprint::rect(Size::new(2, 3));
+        spatial_table.set_footprint(boss, shape.clone()).unwrap();
+        spatial_table
+            .update(boss, (Coord::new(1, 1), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(hero, (Coord::new(3, 1), Layer::Character).into())
+            .unwrap();
+        let default = serde_json::to_string(&spatial_table).unwrap();
+        let from_default: SpatialTable = serde_json::from_str(&default).unwrap();
+        let compact = serde_json::to_string(&Compact(spatial_table)).unwrap();
+        let Compact(from_compact) = serde_json::from_str(&compact).unwrap();
+        for spatial_table in [from_default, from_compact] {
+            assert_eq!(spatial_table.footprint_of(boss), Some(&shape));
+            assert_eq!(spatial_table.footprint_of(hero), None);
+            assert_eq!(
+                spatial_table
+                    .entities_in_rect(Coord::new(0, 0), Size::new(6, 6), Layer::Character)
+                    .filter(|&(_, _, entity)| entity == boss)
+                    .count(),
+                6
+            );
+            assert_eq!(spatial_table.check_invariants(), vec![]);
+        }
+
+        // conflicts are reported at the cell where they happen rather than at the anchor
+        let mut entity_allocator = EntityAllocator::default();
+        let entities = (0..4).map(|_| entity_allocator.alloc()).collect::<Vec<_>>();
+        let invalid = json(
+            &[
+                entry(0, 1, 1, "Character"),
+                entry(1, 0, 0, "Character"),
+                entry(2, 4, 4, "Feature"),
+            ],
+            6,
+        );
+        let footprint = |index: u32, width: u32, height: u32| {
+            format!(
+                r#"[{{"id":{},"index":{}}},{}]"#,
+                index,
+                index,
+                serde_json::to_string(&Footprint::rect(Size::new(width, height))).unwrap()
+            )
+        };
+        let invalid = format!(
+            r#"{},"footprints":[{},{},{}]}}"#,
+            invalid.strip_suffix('}').unwrap(),
+            footprint(1, 2, 2),
+            footprint(2, 3, 1),
+            footprint(3, 2, 2),
+        );
+        let errors =
+            SpatialTable::validate(&mut serde_json::Deserializer::from_str(&invalid)).unwrap();
+        assert_eq!(
+            errors,
+            vec![
+                ValidationError::FootprintWithoutLocation {
+                    entity: entities[3]
+                },
+                ValidationError::OccupiedBy {
+                    entity: entities[1],
+                    occupant: entities[0],
+                    coord: Coord::new(1, 1),
+                },
+                ValidationError::OutOfBounds {
+                    entity: entities[2],
+                    coord: Coord::new(6, 4),
+                },
+            ],
+        );
+
+        // compact data saved before footprints were recorded can still be read
+        let v1 = r#"{"V1":{"size":{"x":2,"y":2},"layers":[{"layer":"Feature","runs":[3,1],"stack_heights":[],"entities":[{"id":0,"index":0}]}],"unlayered":[]}}"#;
+        let Compact(from_v1) = serde_json::from_str(v1).unwrap();
+        assert_eq!(
+            from_v1.entity_at(Coord::new(1, 1), Layer::Feature),
+            Some(EntityAllocator::default().alloc())
+        );
+    }
+
+    #[test]
+    fn migration() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let hero = entity_allocator.alloc();
+        let wall = entity_allocator.alloc();
+        spatial_table
+            .update(hero, (Coord::new(1, 1), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(wall, (Coord::new(2, 2), Layer::Feature).into())
+            .unwrap();
+        let saved = serde_json::to_string(&spatial_table).unwrap();
+
+        // layers are serialized by name, so reordering or renaming layers never silently moves
+        // an entity to the wrong layer
+        assert!(serde_json::from_str::<super::SpatialTable<v2::Layers>>(&saved).is_err());
+        let (migrated, migrations) = super::SpatialTable::<v2::Layers>::deserialize_with_migration(
+            &mut serde_json::Deserializer::from_str(&saved),
+            |name| (name == "Feature").then_some(v2::Layer::Floor),
+        )
+        .unwrap();
+        assert_eq!(
+            migrations,
+            vec![Migration::Mapped {
+                entity: wall,
+                from: "Feature".to_string(),
+                to: v2::Layer::Floor,
+            }],
+        );
+        assert_eq!(
+            *migrated.layers_at_checked(Coord::new(1, 1)),
+            v2::Layers {
+                character: Some(hero),
+                floor: None,
+            },
+        );
+        assert_eq!(migrated.layer_of(wall), Some(v2::Layer::Floor));
+
+        let (migrated, migrations) = super::SpatialTable::<v2::Layers>::deserialize_with_migration(
+            &mut serde_json::Deserializer::from_str(&saved),
+            |_| None,
+        )
+        .unwrap();
+        assert_eq!(
+            migrations,
+            vec![Migration::Dropped {
+                entity: wall,
+                from: "Feature".to_string(),
+            }],
+        );
+        assert_eq!(migrated.location_of(wall), None);
+        assert_eq!(migrated.iter_entities().count(), 1);
+    }
+
+    #[test]
+    fn fallible_deserialize() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let entities = (0..4).map(|_| entity_allocator.alloc()).collect::<Vec<_>>();
+        let entity = entities[0];
+        spatial_table
+            .update(entity, (Coord::new(1, 2), Layer::Feature).into())
+            .unwrap();
+        let valid = serde_json::to_string(&spatial_table).unwrap();
+        assert_eq!(valid, json(&[entry(0, 1, 2, "Feature")], 10));
+        let loaded: SpatialTable = serde_json::from_str(&valid).unwrap();
+        assert_eq!(
+            loaded.layers_at_checked(Coord::new(1, 2)).feature,
+            Some(entity)
+        );
+        assert_eq!(
+            SpatialTable::validate(&mut serde_json::Deserializer::from_str(&valid)).unwrap(),
+            vec![],
+        );
+
+        let invalid = json(
+            &[
+                entry(0, 1, 2, "Feature"),
+                entry(1, 1, 2, "Feature"),
+                entry(2, 1, 2, "Character"),
+                entry(3, 10, 0, "Character"),
+            ],
+            10,
+        );
+        let errors =
+            SpatialTable::validate(&mut serde_json::Deserializer::from_str(&invalid)).unwrap();
+        assert_eq!(
+            errors,
+            vec![
+                ValidationError::OccupiedBy {
+                    entity: entities[1],
+                    occupant: entities[0],
+                    coord: Coord::new(1, 2),
+                },
+                ValidationError::OutOfBounds {
+                    entity: entities[3],
+                    coord: Coord::new(10, 0),
+                },
+            ],
+        );
+        let error = serde_json::from_str::<SpatialTable>(&invalid)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains(&errors[0].to_string()));
+        assert!(error.contains(&errors[1].to_string()));
+    }
 }
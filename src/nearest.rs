@@ -0,0 +1,185 @@
+use crate::{Coord, Entity, Layers, Metric, SpatialTable, Storage};
+
+impl<L: Layers, S: Storage<L>> SpatialTable<L, S> {
+    /// Returns the entity on `layer` which is closest to `coord` according to `metric`, along
+    /// with its coord. Ties are broken in favour of the entity whose coord comes first in
+    /// row-major order, and then by the entity itself.
+    pub fn nearest(
+        &self,
+        coord: Coord,
+        layer: L::Layer,
+        metric: Metric,
+    ) -> Option<(Entity, Coord)> {
+        self.iter_layer(layer)
+            .min_by_key(|&(entity, entity_coord)| sort_key(coord, metric, entity, entity_coord))
+    }
+    /// Returns up to `k` entities on `layer` for which `predicate` returns `true`, ordered by
+    /// their distance from `coord` according to `metric`, along with their coords. If
+    /// `max_distance` is specified, entities further than this are ignored. Ties are broken in
+    /// the same way as [`Self::nearest`].
+    pub fn k_nearest<F>(
+        &self,
+        coord: Coord,
+        layer: L::Layer,
+        metric: Metric,
+        k: usize,
+        max_distance: Option<u32>,
+        mut predicate: F,
+    ) -> Vec<(Entity, Coord)>
+    where
+        F: FnMut(Entity, Coord) -> bool,
+    {
+        let mut candidates = self
+            .iter_layer(layer)
+            .filter(|&(entity, entity_coord)| {
+                max_distance.map_or(true, |max_distance| {
+                    metric.is_within_from(coord, entity_coord, max_distance)
+                }) && predicate(entity, entity_coord)
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_unstable_by_key(|&(entity, entity_coord)| {
+            sort_key(coord, metric, entity, entity_coord)
+        });
+        candidates.truncate(k);
+        candidates
+    }
+}
+
+fn sort_key(
+    origin: Coord,
+    metric: Metric,
+    entity: Entity,
+    coord: Coord,
+) -> (u128, i32, i32, Entity) {
+    (metric.order_key(origin, coord), coord.y, coord.x, entity)
+}
+
+#[cfg(test)]
+mod test {
+    crate::declare_layers_module! {
+        layers {
+            feature: Feature,
+            character: Character,
+        }
+    }
+    use crate::{Coord, Metric, Size};
+    use entity_table::EntityAllocator;
+    use layers::{Layer, Layers};
+    type SpatialTable = crate::SpatialTable<Layers>;
+
+    #[test]
+    fn nearest() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(20, 20));
+        let origin = Coord::new(10, 10);
+        let diagonal = entity_allocator.alloc();
+        let right = entity_allocator.alloc();
+        let left = entity_allocator.alloc();
+        let far = entity_allocator.alloc();
+        let feature = entity_allocator.alloc();
+        spatial_table
+            .update(diagonal, (Coord::new(12, 12), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(right, (Coord::new(13, 10), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(left, (Coord::new(7, 10), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(far, (Coord::new(0, 0), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(feature, (Coord::new(10, 11), Layer::Feature).into())
+            .unwrap();
+
+        assert_eq!(
+            spatial_table.nearest(origin, Layer::Character, Metric::Chebyshev),
+            Some((diagonal, Coord::new(12, 12))),
+        );
+        // left and right are equally distant, so the tie is broken by coord
+        assert_eq!(
+            spatial_table.nearest(origin, Layer::Character, Metric::Manhattan),
+            Some((left, Coord::new(7, 10))),
+        );
+        assert_eq!(
+            spatial_table.nearest(origin, Layer::Feature, Metric::Euclidean),
+            Some((feature, Coord::new(10, 11))),
+        );
+
+        let k_nearest = |metric, k, max_distance| {
+            spatial_table
+                .k_nearest(origin, Layer::Character, metric, k, max_distance, |_, _| {
+                    true
+                })
+                .into_iter()
+                .map(|(entity, _)| entity)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            k_nearest(Metric::Euclidean, 10, None),
+            vec![diagonal, left, right, far],
+        );
+        assert_eq!(k_nearest(Metric::Manhattan, 2, None), vec![left, right]);
+        assert_eq!(
+            k_nearest(Metric::Chebyshev, 10, Some(3)),
+            vec![diagonal, left, right]
+        );
+        assert_eq!(k_nearest(Metric::Manhattan, 10, Some(3)), vec![left, right]);
+        assert_eq!(
+            spatial_table.k_nearest(
+                origin,
+                Layer::Character,
+                Metric::Euclidean,
+                10,
+                None,
+                |entity, _| entity != diagonal,
+            ),
+            vec![
+                (left, Coord::new(7, 10)),
+                (right, Coord::new(13, 10)),
+                (far, Coord::new(0, 0)),
+            ],
+        );
+    }
+
+    #[test]
+    fn distant_origin() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(20, 20));
+        let near = entity_allocator.alloc();
+        let far = entity_allocator.alloc();
+        spatial_table
+            .update(near, (Coord::new(0, 0), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(far, (Coord::new(19, 19), Layer::Character).into())
+            .unwrap();
+        let origin = Coord::new(i32::MIN, i32::MIN);
+        for metric in [Metric::Chebyshev, Metric::Manhattan, Metric::Euclidean] {
+            assert_eq!(
+                spatial_table.nearest(origin, Layer::Character, metric),
+                Some((near, Coord::new(0, 0))),
+            );
+        }
+        assert_eq!(
+            spatial_table.k_nearest(
+                origin,
+                Layer::Character,
+                Metric::Chebyshev,
+                2,
+                Some(1 << 31),
+                |_, _| true,
+            ),
+            vec![(near, Coord::new(0, 0))],
+        );
+        assert_eq!(
+            spatial_table.nearest(
+                Coord::new(i32::MAX, i32::MAX),
+                Layer::Character,
+                Metric::Euclidean
+            ),
+            Some((far, Coord::new(19, 19))),
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use crate::{Entity, Footprint, Layers, Location, SpatialTable, Storage};
+use std::fmt;
+
+/// Receives a notification each time the location or footprint of an entity in a
+/// [`SpatialTable`] changes
+///
+/// Observers must be `Send` and `Sync` so that the table remains `Send` and `Sync`, which
+/// means a closure can't capture an `Rc`, `Cell` or `RefCell`. To share the state an observer
+/// updates with the rest of the program, wrap it in an `Arc<Mutex<_>>` instead:
+///
+/// ```
+/// use spatial_table::{declare_layers_module, Coord, Size, SpatialTable};
+/// use std::sync::{Arc, Mutex};
+///
+/// declare_layers_module! {
+///     layers {
+///         character: Character,
+///     }
+/// }
+/// use layers::{Layer, Layers};
+///
+/// let mut spatial_table = SpatialTable::<Layers>::new(Size::new(10, 10));
+/// let moved = Arc::new(Mutex::new(Vec::new()));
+/// let observer_moved = Arc::clone(&moved);
+/// spatial_table.add_observer(move |entity, _, _| observer_moved.lock().unwrap().push(entity));
+/// let hero = entity_table::EntityAllocator::default().alloc();
+/// spatial_table
+///     .update(hero, (Coord::new(1, 2), Layer::Character).into())
+///     .unwrap();
+/// assert_eq!(*moved.lock().unwrap(), vec![hero]);
+/// ```
+pub trait SpatialObserver<L> {
+    /// Called after each successful mutation, once for each entity whose location changed.
+    /// A location of `None` means the entity had no location.
+    fn location_changed(
+        &mut self,
+        entity: Entity,
+        original_location: Option<Location<L>>,
+        location: Option<Location<L>>,
+    );
+    /// Called after each successful mutation which changed the footprint of an entity,
+    /// including when its footprint is discarded because it was removed from the table. A
+    /// footprint of `None` means the entity covers only its anchor. Does nothing by default.
+    fn footprint_changed(
+        &mut self,
+        _entity: Entity,
+        _original_footprint: Option<&Footprint>,
+        _footprint: Option<&Footprint>,
+    ) {
+    }
+}
+
+impl<L, F> SpatialObserver<L> for F
+where
+    F: FnMut(Entity, Option<Location<L>>, Option<Location<L>>),
+{
+    fn location_changed(
+        &mut self,
+        entity: Entity,
+        original_location: Option<Location<L>>,
+        location: Option<Location<L>>,
+    ) {
+        self(entity, original_location, location)
+    }
+}
+
+/// Identifies an observer registered with [`SpatialTable::add_observer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObserverId(u64);
+
+pub(crate) struct Observers<L> {
+    observers: Vec<(ObserverId, Box<dyn SpatialObserver<L> + Send + Sync>)>,
+    next_id: u64,
+}
+
+impl<L> Default for Observers<L> {
+    fn default() -> Self {
+        Self {
+            observers: Vec::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl<L> fmt::Debug for Observers<L> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.observers.iter().map(|(id, _)| id))
+            .finish()
+    }
+}
+
+impl<L: Copy> Observers<L> {
+    pub(crate) fn notify(
+        &mut self,
+        entity: Entity,
+        original_location: Option<Location<L>>,
+        location: Option<Location<L>>,
+    ) {
+        for (_, observer) in self.observers.iter_mut() {
+            observer.location_changed(entity, original_location, location);
+        }
+    }
+    pub(crate) fn notify_footprint(
+        &mut self,
+        entity: Entity,
+        original_footprint: Option<&Footprint>,
+        footprint: Option<&Footprint>,
+    ) {
+        for (_, observer) in self.observers.iter_mut() {
+            observer.footprint_changed(entity, original_footprint, footprint);
+        }
+    }
+}
+
+impl<L: Layers, S: Storage<L>> SpatialTable<L, S> {
+    /// Registers an observer which is notified of every subsequent change to the table, until
+    /// it's removed with [`Self::remove_observer`]. Observers are notified in the order they
+    /// were added. Observers must be `Send` and `Sync`; see [`SpatialObserver`] for how to
+    /// share state with an observer.
+    pub fn add_observer<O>(&mut self, observer: O) -> ObserverId
+    where
+        O: SpatialObserver<L::Layer> + Send + Sync + 'static,
+    {
+        let id = ObserverId(self.observers.next_id);
+        self.observers.next_id += 1;
+        self.observers.observers.push((id, Box::new(observer)));
+        id
+    }
+    /// Unregisters an observer, returning it if it was registered
+    pub fn remove_observer(
+        &mut self,
+        id: ObserverId,
+    ) -> Option<Box<dyn SpatialObserver<L::Layer> + Send + Sync>> {
+        let index = self
+            .observers
+            .observers
+            .iter()
+            .position(|&(observer_id, _)| observer_id == id)?;
+        Some(self.observers.observers.remove(index).1)
+    }
+    /// Runs `f` with every registered observer detached, so changes made by `f` aren't observed
+    pub fn without_observers<R, F: FnOnce(&mut Self) -> R>(&mut self, f: F) -> R {
+        let observers = std::mem::take(&mut self.observers.observers);
+        let result = f(self);
+        let added = std::mem::replace(&mut self.observers.observers, observers);
+        self.observers.observers.extend(added);
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    crate::declare_layers_module! {
+        layers {
+            feature: Feature,
+            character: Character,
+        }
+    }
+    use crate::{Coord, Entity, Footprint, Location, Size, SpatialObserver};
+    use entity_table::EntityAllocator;
+    use layers::{Layer, Layers};
+    use std::sync::{Arc, Mutex};
+    type SpatialTable = crate::SpatialTable<Layers>;
+    type Changes = Vec<(Entity, Option<Location<Layer>>, Option<Location<Layer>>)>;
+
+    #[test]
+    fn observers() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let hero = entity_allocator.alloc();
+        let villain = entity_allocator.alloc();
+        let changes = Arc::new(Mutex::new(Changes::new()));
+        let id = spatial_table.add_observer({
+            let changes = Arc::clone(&changes);
+            move |entity, original_location, location| {
+                changes
+                    .lock()
+                    .unwrap()
+                    .push((entity, original_location, location))
+            }
+        });
+        let a: Location<Layer> = (Coord::new(1, 1), Layer::Character).into();
+        let b: Location<Layer> = (Coord::new(2, 2), Layer::Character).into();
+        spatial_table.update(hero, a).unwrap();
+        spatial_table.update(villain, b).unwrap();
+        spatial_table.update(hero, b).unwrap_err();
+        spatial_table.without_observers(|spatial_table| spatial_table.remove(villain));
+        spatial_table.swap(hero, hero).unwrap();
+        spatial_table.update_coord(hero, b.coord).unwrap();
+        spatial_table.clear();
+        assert_eq!(
+            *changes.lock().unwrap(),
+            vec![
+                (hero, None, Some(a)),
+                (villain, None, Some(b)),
+                (hero, Some(a), Some(b)),
+                (hero, Some(b), None),
+            ],
+        );
+
+        assert!(spatial_table.remove_observer(id).is_some());
+        assert!(spatial_table.remove_observer(id).is_none());
+        spatial_table.update(hero, a).unwrap();
+        assert_eq!(changes.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn footprint_changes() {
+        #[derive(Default)]
+        struct Footprints(Vec<(Entity, Option<Footprint>, Option<Footprint>)>);
+        impl SpatialObserver<Layer> for Arc<Mutex<Footprints>> {
+            fn location_changed(
+                &mut self,
+                _: Entity,
+                _: Option<Location<Layer>>,
+                _: Option<Location<Layer>>,
+            ) {
+            }
+            fn footprint_changed(
+                &mut self,
+                entity: Entity,
+                original_footprint: Option<&Footprint>,
+                footprint: Option<&Footprint>,
+            ) {
+                self.lock().unwrap().0.push((
+                    entity,
+                    original_footprint.cloned(),
+                    footprint.cloned(),
+                ));
+            }
+        }
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let boss = entity_allocator.alloc();
+        let changes = Arc::new(Mutex::new(Footprints::default()));
+        spatial_table.add_observer(Arc::clone(&changes));
+        let shape = Footprint::rect(Size::new(2, 2));
+        spatial_table.set_footprint(boss, shape.clone()).unwrap();
+        spatial_table
+            .update(boss, (Coord::new(1, 1), Layer::Character).into())
+            .unwrap();
+        spatial_table.set_footprint(boss, shape.clone()).unwrap();
+        spatial_table.remove(boss);
+        assert_eq!(
+            changes.lock().unwrap().0,
+            vec![(boss, None, Some(shape.clone())), (boss, Some(shape), None),],
+        );
+    }
+
+    #[test]
+    fn send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<SpatialTable>();
+        assert_send_sync::<crate::ChunkedSpatialTable<Layers>>();
+    }
+}
@@ -0,0 +1,305 @@
+use crate::{Coord, LayerSet, Layers, Size, SpatialTable, Storage};
+
+const WORD_BITS: usize = u64::BITS as usize;
+
+/// A packed bitmap with one bit per cell of the spatial grid, set for each cell where a layer
+/// is occupied. Each row is stored as a whole number of `u64` words. Bit `i` of word `j` of a
+/// row corresponds to the cell with x coordinate `j * 64 + i`, and bits beyond the width of
+/// the grid are always clear.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OccupancyBitmap {
+    size: Size,
+    words_per_row: usize,
+    words: Vec<u64>,
+}
+
+impl OccupancyBitmap {
+    fn new(size: Size) -> Self {
+        let words_per_row = (size.width() as usize).div_ceil(WORD_BITS);
+        Self {
+            size,
+            words_per_row,
+            words: vec![0; words_per_row * size.height() as usize],
+        }
+    }
+    fn word_and_bit(&self, coord: Coord) -> Option<(usize, u64)> {
+        if coord.is_valid(self.size) {
+            let x = coord.x as usize;
+            let index = coord.y as usize * self.words_per_row + x / WORD_BITS;
+            Some((index, 1 << (x % WORD_BITS)))
+        } else {
+            None
+        }
+    }
+    fn set(&mut self, coord: Coord, occupied: bool) {
+        if let Some((index, bit)) = self.word_and_bit(coord) {
+            if occupied {
+                self.words[index] |= bit;
+            } else {
+                self.words[index] &= !bit;
+            }
+        }
+    }
+    fn clear(&mut self) {
+        self.words.fill(0);
+    }
+    pub fn size(&self) -> Size {
+        self.size
+    }
+    pub fn words_per_row(&self) -> usize {
+        self.words_per_row
+    }
+    /// All the words of the bitmap, in row-major order
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+    /// The words of a single row
+    pub fn row(&self, y: u32) -> &[u64] {
+        let start = y as usize * self.words_per_row;
+        &self.words[start..(start + self.words_per_row)]
+    }
+    /// Returns `false` for coords outside the grid
+    pub fn get(&self, coord: Coord) -> bool {
+        self.word_and_bit(coord)
+            .is_some_and(|(index, bit)| self.words[index] & bit != 0)
+    }
+    /// Returns `true` iff no cell in the axis-aligned rectangle with the given top-left corner
+    /// and size is occupied. Cells outside the grid are ignored.
+    pub fn is_rect_free(&self, top_left: Coord, size: Size) -> bool {
+        let start = top_left.pairwise_max(Coord::new(0, 0));
+        let end = (top_left + size).pairwise_min(Coord::new(0, 0) + self.size);
+        if start.x >= end.x || start.y >= end.y {
+            return true;
+        }
+        let (x_start, x_end) = (start.x as usize, end.x as usize);
+        let (first, last) = (x_start / WORD_BITS, (x_end - 1) / WORD_BITS);
+        (start.y..end.y).all(|y| {
+            let row = self.row(y as u32);
+            (first..=last).all(|i| {
+                let low = if i == first { x_start % WORD_BITS } else { 0 };
+                let high = if i == last {
+                    (x_end - 1) % WORD_BITS + 1
+                } else {
+                    WORD_BITS
+                };
+                let mask = (u64::MAX >> (WORD_BITS - (high - low))) << low;
+                row[i] & mask == 0
+            })
+        })
+    }
+    /// Iterates over the coords of occupied cells in row-major order, skipping a whole word at
+    /// a time where no cells are occupied
+    pub fn iter(&self) -> OccupiedCoords<'_> {
+        OccupiedCoords {
+            bitmap: self,
+            index: 0,
+            word: self.words.first().copied().unwrap_or(0),
+        }
+    }
+}
+
+/// Iterator over the coords of occupied cells in an [`OccupancyBitmap`]
+pub struct OccupiedCoords<'a> {
+    bitmap: &'a OccupancyBitmap,
+    index: usize,
+    /// The bits of the current word which haven't been yielded yet
+    word: u64,
+}
+
+impl<'a> Iterator for OccupiedCoords<'a> {
+    type Item = Coord;
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.word == 0 {
+            self.index += 1;
+            self.word = *self.bitmap.words.get(self.index)?;
+        }
+        let bit = self.word.trailing_zeros() as usize;
+        self.word &= self.word - 1;
+        let x = (self.index % self.bitmap.words_per_row) * WORD_BITS + bit;
+        let y = self.index / self.bitmap.words_per_row;
+        Some(Coord::new(x as i32, y as i32))
+    }
+}
+
+impl<L: Layers> SpatialTable<L> {
+    /// Starts maintaining an [`OccupancyBitmap`] for each layer, which speeds up
+    /// [`Self::is_region_free`]. Has no effect if the bitmaps are already enabled.
+    pub fn enable_occupancy_bitmaps(&mut self) {
+        if self.occupancy.is_none() {
+            self.build_occupancy_bitmaps();
+        }
+    }
+    /// Populates the occupancy bitmaps from the contents of the spatial grid
+    pub(crate) fn build_occupancy_bitmaps(&mut self) {
+        let mut bitmaps = L::LAYERS
+            .iter()
+            .map(|_| OccupancyBitmap::new(self.grid_size()))
+            .collect::<Vec<_>>();
+        for (coord, cell) in self.enumerate() {
+            for &layer in L::LAYERS {
+                if cell.select_field(layer).is_some() {
+                    bitmaps[L::layer_index(layer)].set(coord, true);
+                }
+            }
+        }
+        self.occupancy = Some(bitmaps);
+    }
+}
+
+impl<L: Layers, S: Storage<L>> SpatialTable<L, S> {
+    pub fn disable_occupancy_bitmaps(&mut self) {
+        self.occupancy = None;
+    }
+    pub fn has_occupancy_bitmaps(&self) -> bool {
+        self.occupancy.is_some()
+    }
+    /// The occupancy bitmap of a layer, if bitmaps are enabled
+    pub fn occupancy_bitmap(&self, layer: L::Layer) -> Option<&OccupancyBitmap> {
+        self.occupancy
+            .as_ref()
+            .map(|bitmaps| &bitmaps[L::layer_index(layer)])
+    }
+    /// Returns `true` iff none of `layers` is occupied in any cell of the axis-aligned
+    /// rectangle with the given top-left corner and size. Cells outside the spatial grid are
+    /// ignored. Uses the occupancy bitmaps if they are enabled, and otherwise visits every
+    /// cell in the rectangle.
+    pub fn is_region_free<M: Into<LayerSet<L>>>(
+        &self,
+        top_left: Coord,
+        size: Size,
+        layers: M,
+    ) -> bool {
+        let layers = layers.into();
+        match self.occupancy.as_ref() {
+            Some(bitmaps) => layers
+                .iter()
+                .all(|layer| bitmaps[L::layer_index(layer)].is_rect_free(top_left, size)),
+            None => self
+                .entities_in_rect(top_left, size, layers)
+                .next()
+                .is_none(),
+        }
+    }
+    /// Brings the occupancy bitmap of `layer` up to date with the cell at `coord`
+    pub(crate) fn update_occupancy(&mut self, coord: Coord, layer: L::Layer) {
+        if let Some(bitmaps) = self.occupancy.as_mut() {
+            let occupied = self
+                .spatial_grid
+                .get(coord)
+                .is_some_and(|cell| cell.select_field(layer).is_some());
+            bitmaps[L::layer_index(layer)].set(coord, occupied);
+        }
+    }
+    pub(crate) fn clear_occupancy(&mut self) {
+        for bitmap in self.occupancy.iter_mut().flatten() {
+            bitmap.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    crate::declare_layers_module! {
+        layers {
+            feature: Feature,
+            item: Item [stack],
+            character: Character,
+        }
+    }
+    use crate::{Coord, Size};
+    use entity_table::EntityAllocator;
+    use layers::{Layer, Layers};
+    type SpatialTable = crate::SpatialTable<Layers>;
+
+    #[test]
+    fn occupancy_bitmaps() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(100, 4));
+        let wall = entity_allocator.alloc();
+        spatial_table
+            .update(wall, (Coord::new(70, 1), Layer::Feature).into())
+            .unwrap();
+        assert_eq!(spatial_table.occupancy_bitmap(Layer::Feature), None);
+        let size = Size::new(20, 2);
+        assert!(!spatial_table.is_region_free(Coord::new(60, 0), size, Layer::Feature));
+        spatial_table.enable_occupancy_bitmaps();
+        let bitmap = spatial_table.occupancy_bitmap(Layer::Feature).unwrap();
+        assert_eq!(bitmap.words_per_row(), 2);
+        assert_eq!(bitmap.row(1), &[0, 1 << 6]);
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), vec![Coord::new(70, 1)]);
+
+        let hero = entity_allocator.alloc();
+        let items = [entity_allocator.alloc(), entity_allocator.alloc()];
+        spatial_table
+            .update(hero, (Coord::new(63, 3), Layer::Character).into())
+            .unwrap();
+        for item in items {
+            spatial_table
+                .update(item, (Coord::new(0, 0), Layer::Item).into())
+                .unwrap();
+        }
+        spatial_table.update_coord(wall, Coord::new(64, 2)).unwrap();
+        type LayerSet = layers::LayerSet;
+        let free = |spatial_table: &SpatialTable, x, y, width, height, layers: LayerSet| {
+            spatial_table.is_region_free(Coord::new(x, y), Size::new(width, height), layers)
+        };
+        for spatial_table in [&spatial_table, &{
+            let mut without = SpatialTable::new(Size::new(100, 4));
+            without
+                .apply_diff(&without.snapshot().diff(&spatial_table.snapshot()))
+                .unwrap();
+            without
+        }] {
+            assert!(free(spatial_table, 0, 0, 64, 2, Layer::Feature.into()));
+            assert!(!free(spatial_table, 0, 0, 65, 3, Layer::Feature.into()));
+            assert!(free(
+                spatial_table,
+                65,
+                0,
+                35,
+                4,
+                Layer::Feature | Layer::Character
+            ));
+            assert!(!free(
+                spatial_table,
+                63,
+                3,
+                1,
+                1,
+                Layer::Feature | Layer::Character
+            ));
+            assert!(free(
+                spatial_table,
+                62,
+                3,
+                1,
+                1,
+                Layer::Feature | Layer::Character
+            ));
+            assert!(!free(spatial_table, -10, -10, 11, 11, None.into()));
+            assert!(free(spatial_table, 100, 0, 10, 10, None.into()));
+        }
+
+        spatial_table.remove(items[1]);
+        assert!(!spatial_table.is_region_free(Coord::new(0, 0), Size::new(1, 1), Layer::Item));
+        spatial_table.clear_layer(items[0]).unwrap();
+        assert!(spatial_table.is_region_free(Coord::new(0, 0), Size::new(1, 1), Layer::Item));
+        assert_eq!(spatial_table.check_invariants(), vec![]);
+
+        spatial_table.resize(Size::new(70, 5), Coord::new(1, 1));
+        let bitmap = spatial_table.occupancy_bitmap(Layer::Feature).unwrap();
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), vec![Coord::new(65, 3)]);
+        assert_eq!(spatial_table.check_invariants(), vec![]);
+
+        spatial_table.clear();
+        assert!(spatial_table.is_region_free(Coord::new(0, 0), Size::new(70, 5), None));
+        assert_eq!(
+            spatial_table
+                .occupancy_bitmap(Layer::Character)
+                .unwrap()
+                .iter()
+                .next(),
+            None
+        );
+    }
+}
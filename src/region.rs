@@ -0,0 +1,407 @@
+use crate::{Coord, Entity, LayerSet, Layers, Size, SpatialTable, Stack, Storage};
+use grid_2d::{CoordIter, Grid};
+
+/// A way of measuring the distance between two coords
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// The larger of the horizontal and vertical distances
+    Chebyshev,
+    /// The sum of the horizontal and vertical distances
+    Manhattan,
+    /// The straight-line distance
+    Euclidean,
+}
+
+impl Metric {
+    /// Returns `true` iff a coord offset by `delta` from some point is within `radius` of
+    /// that point.
+    pub fn is_within(self, delta: Coord, radius: u32) -> bool {
+        self.is_within_components(
+            delta.x.unsigned_abs() as u64,
+            delta.y.unsigned_abs() as u64,
+            radius,
+        )
+    }
+    /// Like [`Self::is_within`], for the offset from `origin` to `coord`, which may not fit in
+    /// a `Coord`.
+    pub(crate) fn is_within_from(self, origin: Coord, coord: Coord, radius: u32) -> bool {
+        let (dx, dy) = components(origin, coord);
+        self.is_within_components(dx, dy, radius)
+    }
+    fn is_within_components(self, dx: u64, dy: u64, radius: u32) -> bool {
+        let radius = radius as u64;
+        match self {
+            Self::Chebyshev => dx.max(dy) <= radius,
+            Self::Manhattan => dx + dy <= radius,
+            Self::Euclidean => {
+                (dx as u128 * dx as u128) + (dy as u128 * dy as u128) <= (radius * radius) as u128
+            }
+        }
+    }
+    /// A value which orders coords by their distance from `origin` in this metric. For the
+    /// euclidean metric this is the square of the distance.
+    pub(crate) fn order_key(self, origin: Coord, coord: Coord) -> u128 {
+        let (dx, dy) = components(origin, coord);
+        let (dx, dy) = (dx as u128, dy as u128);
+        match self {
+            Self::Chebyshev => dx.max(dy),
+            Self::Manhattan => dx + dy,
+            Self::Euclidean => dx * dx + dy * dy,
+        }
+    }
+}
+
+/// The absolute horizontal and vertical distances between two coords
+fn components(a: Coord, b: Coord) -> (u64, u64) {
+    (
+        (b.x as i64 - a.x as i64).unsigned_abs(),
+        (b.y as i64 - a.y as i64).unsigned_abs(),
+    )
+}
+
+/// Iterates over the coords of a rectangle in row-major order
+pub struct RectCoords {
+    inner: RectCoordsInner,
+}
+
+enum RectCoordsInner {
+    Rect {
+        top_left: Coord,
+        iter: CoordIter,
+    },
+    /// The allocated coords within the rectangle, for storage which only allocates some cells
+    Coords(std::vec::IntoIter<Coord>),
+}
+
+impl RectCoords {
+    /// The rectangle is clipped to the cells allocated by `storage`
+    fn new_clipped<L: Layers, S: Storage<L>>(top_left: Coord, size: Size, storage: &S) -> Self {
+        let start = (top_left.x as i64, top_left.y as i64);
+        let end = (
+            start.0 + size.width() as i64,
+            start.1 + size.height() as i64,
+        );
+        Self::from_corners(start, end, storage)
+    }
+    /// The rectangle from `start` (inclusive) to `end` (exclusive), clipped to the cells
+    /// allocated by `storage`. The corners are 64-bit so they can lie outside the range of a
+    /// `Coord` without overflowing.
+    fn from_corners<L: Layers, S: Storage<L>>(
+        start: (i64, i64),
+        end: (i64, i64),
+        storage: &S,
+    ) -> Self {
+        let regions = match storage.allocated_regions() {
+            Some(regions) => regions,
+            None => return Self::from_corners_in_bounds(start, end, storage.bounds()),
+        };
+        let mut coords = Vec::new();
+        for (top_left, size) in regions {
+            let region_start = (top_left.x as i64, top_left.y as i64);
+            let region_end = (
+                region_start.0 + size.width() as i64,
+                region_start.1 + size.height() as i64,
+            );
+            coords.extend(Self::from_corners_in_bounds(
+                (start.0.max(region_start.0), start.1.max(region_start.1)),
+                (end.0.min(region_end.0), end.1.min(region_end.1)),
+                None,
+            ));
+        }
+        coords.sort_unstable_by_key(|coord| (coord.y, coord.x));
+        Self {
+            inner: RectCoordsInner::Coords(coords.into_iter()),
+        }
+    }
+    /// Like [`Self::from_corners`], but clipped to the bounds of a grid of size `bounds`, or to
+    /// the range of a `Coord` if there are no bounds
+    fn from_corners_in_bounds(start: (i64, i64), end: (i64, i64), bounds: Option<Size>) -> Self {
+        let (min, max) = match bounds {
+            Some(bounds) => ((0, 0), (bounds.width() as i64, bounds.height() as i64)),
+            None => (
+                (i32::MIN as i64, i32::MIN as i64),
+                (i32::MAX as i64, i32::MAX as i64),
+            ),
+        };
+        let start = (start.0.max(min.0), start.1.max(min.1));
+        let end = (end.0.min(max.0), end.1.min(max.1));
+        // iterating over a size with zero width and non-zero height never terminates
+        let size = if end.0 > start.0 && end.1 > start.1 {
+            let max_dimension = i32::MAX as i64;
+            Size::new(
+                (end.0 - start.0).min(max_dimension) as u32,
+                (end.1 - start.1).min(max_dimension) as u32,
+            )
+        } else {
+            Size::new(0, 0)
+        };
+        Self {
+            inner: RectCoordsInner::Rect {
+                top_left: Coord::new(start.0 as i32, start.1 as i32),
+                iter: size.coord_iter_row_major(),
+            },
+        }
+    }
+}
+
+impl Iterator for RectCoords {
+    type Item = Coord;
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.inner {
+            RectCoordsInner::Rect { top_left, iter } => iter.next().map(|coord| *top_left + coord),
+            RectCoordsInner::Coords(iter) => iter.next(),
+        }
+    }
+}
+
+/// Iterates over the coords within a given distance of a centre coord in row-major order
+pub struct RadiusCoords {
+    centre: Coord,
+    radius: u32,
+    metric: Metric,
+    rect_coords: RectCoords,
+}
+
+impl Iterator for RadiusCoords {
+    type Item = Coord;
+    fn next(&mut self) -> Option<Self::Item> {
+        let Self {
+            centre,
+            radius,
+            metric,
+            ..
+        } = *self;
+        self.rect_coords
+            .find(|&coord| metric.is_within_from(centre, coord, radius))
+    }
+}
+
+/// Iterates over the `(Coord, Layer, Entity)` triples on a set of layers at a sequence of
+/// coords. Coords outside the spatial grid are skipped. Every entity in
+/// the stack of a stack layer is yielded, from the bottom of the stack to the top.
+pub struct EntitiesIn<'a, L: Layers, C, S: Storage<L> = Grid<L>> {
+    spatial_table: &'a SpatialTable<L, S>,
+    coords: C,
+    layers: LayerSet<L>,
+    cell: Option<(Coord, &'a L)>,
+    layer_index: usize,
+    stack: Option<(L::Layer, Stack<'a>)>,
+}
+
+impl<'a, L: Layers, C: Iterator<Item = Coord>, S: Storage<L>> Iterator for EntitiesIn<'a, L, C, S> {
+    type Item = (Coord, L::Layer, Entity);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (coord, cell) = match self.cell {
+                Some(cell) => cell,
+                None => {
+                    let coord = self.coords.next()?;
+                    if let Some(cell) = self.spatial_table.layers_at(coord) {
+                        self.cell = Some((coord, cell.layers()));
+                        self.layer_index = 0;
+                    }
+                    continue;
+                }
+            };
+            if let Some((layer, stack)) = self.stack.as_mut() {
+                if let Some(entity) = stack.next() {
+                    return Some((coord, *layer, entity));
+                }
+                self.stack = None;
+            }
+            let layer = match L::LAYERS.get(self.layer_index) {
+                Some(&layer) => layer,
+                None => {
+                    self.cell = None;
+                    continue;
+                }
+            };
+            self.layer_index += 1;
+            if !self.layers.contains(layer) {
+                continue;
+            }
+            if L::is_stack(layer) {
+                self.stack = Some((layer, self.spatial_table.stack_at(coord, layer)));
+            } else if let Some(entity) = *cell.select_field(layer) {
+                return Some((coord, layer, entity));
+            }
+        }
+    }
+}
+
+impl<L: Layers, S: Storage<L>> SpatialTable<L, S> {
+    /// Iterates over the `(Layer, Entity)` pairs on `layers` at a coord. A single layer or
+    /// `None`, meaning every layer, may be passed in place of a [`LayerSet`].
+    pub fn entities_at<M: Into<LayerSet<L>>>(
+        &self,
+        coord: Coord,
+        layers: M,
+    ) -> impl Iterator<Item = (L::Layer, Entity)> + '_ {
+        self.entities_at_coords(Some(coord), layers)
+            .map(|(_, layer, entity)| (layer, entity))
+    }
+    /// Iterates over the entities on `layers` at each coord yielded by `coords`
+    pub fn entities_at_coords<C: IntoIterator<Item = Coord>, M: Into<LayerSet<L>>>(
+        &self,
+        coords: C,
+        layers: M,
+    ) -> EntitiesIn<'_, L, C::IntoIter, S> {
+        EntitiesIn {
+            spatial_table: self,
+            coords: coords.into_iter(),
+            layers: layers.into(),
+            cell: None,
+            layer_index: 0,
+            stack: None,
+        }
+    }
+    /// Iterates over the entities on `layers` in the axis-aligned rectangle with the given
+    /// top-left corner and size
+    pub fn entities_in_rect<M: Into<LayerSet<L>>>(
+        &self,
+        top_left: Coord,
+        size: Size,
+        layers: M,
+    ) -> EntitiesIn<'_, L, RectCoords, S> {
+        let coords = RectCoords::new_clipped(top_left, size, &self.spatial_grid);
+        self.entities_at_coords(coords, layers)
+    }
+    /// Iterates over the entities on `layers` within `radius` of `centre` according to
+    /// `metric`
+    pub fn entities_in_radius<M: Into<LayerSet<L>>>(
+        &self,
+        centre: Coord,
+        radius: u32,
+        metric: Metric,
+        layers: M,
+    ) -> EntitiesIn<'_, L, RadiusCoords, S> {
+        let (x, y, radius_i64) = (centre.x as i64, centre.y as i64, radius as i64);
+        let coords = RadiusCoords {
+            centre,
+            radius,
+            metric,
+            rect_coords: RectCoords::from_corners(
+                (x - radius_i64, y - radius_i64),
+                (x + radius_i64 + 1, y + radius_i64 + 1),
+                &self.spatial_grid,
+            ),
+        };
+        self.entities_at_coords(coords, layers)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    crate::declare_layers_module! {
+        layers {
+            feature: Feature,
+            item: Item [stack],
+            character: Character,
+        }
+    }
+    use super::Metric;
+    use crate::{Coord, Size};
+    use entity_table::EntityAllocator;
+    use layers::{Layer, Layers};
+    type SpatialTable = crate::SpatialTable<Layers>;
+
+    #[test]
+    fn region_queries() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let wall = entity_allocator.alloc();
+        let item_a = entity_allocator.alloc();
+        let item_b = entity_allocator.alloc();
+        let hero = entity_allocator.alloc();
+        let villain = entity_allocator.alloc();
+        spatial_table
+            .update(wall, (Coord::new(0, 0), Layer::Feature).into())
+            .unwrap();
+        spatial_table
+            .update(item_a, (Coord::new(2, 2), Layer::Item).into())
+            .unwrap();
+        spatial_table
+            .update(item_b, (Coord::new(2, 2), Layer::Item).into())
+            .unwrap();
+        spatial_table
+            .update(hero, (Coord::new(2, 2), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(villain, (Coord::new(4, 3), Layer::Character).into())
+            .unwrap();
+
+        assert_eq!(
+            spatial_table
+                .entities_in_rect(Coord::new(-5, -5), Size::new(8, 8), None)
+                .collect::<Vec<_>>(),
+            vec![
+                (Coord::new(0, 0), Layer::Feature, wall),
+                (Coord::new(2, 2), Layer::Item, item_a),
+                (Coord::new(2, 2), Layer::Item, item_b),
+                (Coord::new(2, 2), Layer::Character, hero),
+            ],
+        );
+        assert_eq!(
+            spatial_table
+                .entities_in_rect(Coord::new(0, 0), Size::new(10, 10), Some(Layer::Character))
+                .collect::<Vec<_>>(),
+            vec![
+                (Coord::new(2, 2), Layer::Character, hero),
+                (Coord::new(4, 3), Layer::Character, villain),
+            ],
+        );
+
+        let in_radius = |metric| {
+            spatial_table
+                .entities_in_radius(Coord::new(2, 2), 2, metric, Some(Layer::Character))
+                .map(|(_, _, entity)| entity)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(in_radius(Metric::Chebyshev), vec![hero, villain]);
+        assert_eq!(in_radius(Metric::Manhattan), vec![hero]);
+        assert_eq!(in_radius(Metric::Euclidean), vec![hero]);
+        assert_eq!(
+            spatial_table
+                .entities_in_radius(
+                    Coord::new(2, 2),
+                    3,
+                    Metric::Euclidean,
+                    Some(Layer::Character)
+                )
+                .count(),
+            2,
+        );
+        assert_eq!(
+            spatial_table
+                .entities_in_radius(Coord::new(2, 2), u32::MAX, Metric::Euclidean, None)
+                .count(),
+            5,
+        );
+        assert_eq!(
+            spatial_table
+                .entities_in_radius(Coord::new(i32::MAX, 0), u32::MAX, Metric::Chebyshev, None)
+                .count(),
+            5,
+        );
+
+        assert_eq!(
+            spatial_table
+                .entities_in_radius(Coord::new(i32::MIN, 0), 1 << 31, Metric::Chebyshev, None)
+                .collect::<Vec<_>>(),
+            vec![(Coord::new(0, 0), Layer::Feature, wall)],
+        );
+
+        assert_eq!(
+            spatial_table
+                .entities_at_coords(
+                    vec![Coord::new(-1, 0), Coord::new(4, 3), Coord::new(0, 0)],
+                    None,
+                )
+                .collect::<Vec<_>>(),
+            vec![
+                (Coord::new(4, 3), Layer::Character, villain),
+                (Coord::new(0, 0), Layer::Feature, wall),
+            ],
+        );
+    }
+}
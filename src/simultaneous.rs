@@ -0,0 +1,246 @@
+use crate::{Coord, Entity, Layers, Location, SpatialTable, Storage, UpdateError};
+use std::collections::{HashMap, HashSet};
+
+/// A reason an entity couldn't move during [`SpatialTable::update_coords_simultaneous`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveConflict {
+    /// The destination is occupied by an entity which isn't moving out of it
+    OccupiedBy { entity: Entity, occupant: Entity },
+    /// The destination is outside the bounds of the spatial grid
+    DestinationOutOfBounds { entity: Entity },
+    /// Another entity is trying to move into the same location
+    Contested { entity: Entity, rival: Entity },
+}
+
+impl MoveConflict {
+    pub fn entity(&self) -> Entity {
+        match *self {
+            Self::OccupiedBy { entity, .. }
+            | Self::DestinationOutOfBounds { entity }
+            | Self::Contested { entity, .. } => entity,
+        }
+    }
+}
+
+impl<L: Layers, S: Storage<L>> SpatialTable<L, S> {
+    /// Moves a collection of entities to new coords as though they all moved at the same time.
+    /// Entities may move into cells being vacated by other moving entities, so chains of
+    /// entities following each other and cycles of entities rotating between cells are both
+    /// allowed. If multiple entities try to move into the same location, none of them move. An
+    /// entity whose destination remains occupied doesn't move, which may in turn prevent other
+    /// entities from moving into the cell it would have vacated. All other entities are moved,
+    /// and a conflict is returned for each entity which didn't move. If an entity appears more
+    /// than once, only its last intended destination is considered.
+    pub fn update_coords_simultaneous<I>(&mut self, intents: I) -> Vec<MoveConflict>
+    where
+        I: IntoIterator<Item = (Entity, Coord)>,
+    {
+        let mut moves: Vec<(Entity, Option<Location<L::Layer>>)> = Vec::new();
+        let mut move_index_by_entity: HashMap<Entity, usize> = HashMap::new();
+        for (entity, coord) in intents {
+            let layer = self.layer_of(entity);
+            let location = Some(Location { coord, layer });
+            if let Some(&index) = move_index_by_entity.get(&entity) {
+                moves[index].1 = location;
+            } else {
+                move_index_by_entity.insert(entity, moves.len());
+                moves.push((entity, location));
+            }
+        }
+        moves.retain(|&(entity, location)| self.coord_of(entity) != location.map(|l| l.coord));
+        let mut conflicts = Vec::new();
+        let mut entities_by_destination: HashMap<_, Vec<Entity>> = HashMap::new();
+        for &(entity, location) in &moves {
+            if let Some(Location {
+                coord,
+                layer: Some(layer),
+            }) = location
+            {
+                if !L::is_stack(layer) {
+                    entities_by_destination
+                        .entry((coord, layer))
+                        .or_default()
+                        .push(entity);
+                }
+            }
+        }
+        let mut contested = HashMap::new();
+        for entities in entities_by_destination.values() {
+            if entities.len() > 1 {
+                for (i, &entity) in entities.iter().enumerate() {
+                    let rival = entities[if i == 0 { 1 } else { 0 }];
+                    contested.insert(entity, rival);
+                }
+            }
+        }
+        moves.retain(|&(entity, _)| {
+            if let Some(&rival) = contested.get(&entity) {
+                conflicts.push(MoveConflict::Contested { entity, rival });
+                false
+            } else {
+                true
+            }
+        });
+        loop {
+            let errors = self.check_relocate(&moves);
+            if errors.is_empty() {
+                break;
+            }
+            let mut rejected = HashSet::new();
+            for (entity, error) in errors {
+                rejected.insert(entity);
+                conflicts.push(match error {
+                    UpdateError::OccupiedBy(occupant) => {
+                        MoveConflict::OccupiedBy { entity, occupant }
+                    }
+                    UpdateError::DestinationOutOfBounds => {
+                        MoveConflict::DestinationOutOfBounds { entity }
+                    }
+                });
+            }
+            moves.retain(|(entity, _)| !rejected.contains(entity));
+        }
+        self.relocate(&moves)
+            .expect("moves were checked for conflicts");
+        conflicts
+    }
+}
+
+#[cfg(test)]
+mod test {
+    crate::declare_layers_module! {
+        layers {
+            feature: Feature,
+            character: Character,
+        }
+    }
+    use super::MoveConflict;
+    use crate::{Coord, Size};
+    use entity_table::EntityAllocator;
+    use layers::{Layer, Layers};
+    type SpatialTable = crate::SpatialTable<Layers>;
+
+    #[test]
+    fn chains_and_cycles() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let conga = (0..4).map(|_| entity_allocator.alloc()).collect::<Vec<_>>();
+        let cycle = (0..3).map(|_| entity_allocator.alloc()).collect::<Vec<_>>();
+        for (i, &entity) in conga.iter().enumerate() {
+            spatial_table
+                .update(entity, (Coord::new(i as i32, 0), Layer::Character).into())
+                .unwrap();
+        }
+        let cycle_coords = [Coord::new(0, 5), Coord::new(1, 5), Coord::new(1, 6)];
+        for (&entity, &coord) in cycle.iter().zip(cycle_coords.iter()) {
+            spatial_table
+                .update(entity, (coord, Layer::Character).into())
+                .unwrap();
+        }
+
+        // each member of the conga line steps into the cell of the one in front, with the
+        // leader listed last
+        let conga_intents = conga
+            .iter()
+            .enumerate()
+            .map(|(i, &entity)| (entity, Coord::new(i as i32 + 1, 0)));
+        let cycle_intents = cycle
+            .iter()
+            .enumerate()
+            .map(|(i, &entity)| (entity, cycle_coords[(i + 1) % cycle_coords.len()]));
+        let conflicts =
+            spatial_table.update_coords_simultaneous(conga_intents.chain(cycle_intents));
+        assert_eq!(conflicts, vec![]);
+
+        for (i, &entity) in conga.iter().enumerate() {
+            assert_eq!(
+                spatial_table.coord_of(entity),
+                Some(Coord::new(i as i32 + 1, 0))
+            );
+        }
+        for (i, &entity) in cycle.iter().enumerate() {
+            let coord = cycle_coords[(i + 1) % cycle_coords.len()];
+            assert_eq!(spatial_table.coord_of(entity), Some(coord));
+            assert_eq!(
+                spatial_table.layers_at_checked(coord).character,
+                Some(entity)
+            );
+        }
+    }
+
+    #[test]
+    fn blocked_chains_and_contested_cells() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let leader = entity_allocator.alloc();
+        let follower = entity_allocator.alloc();
+        let blocker = entity_allocator.alloc();
+        let rival_a = entity_allocator.alloc();
+        let rival_b = entity_allocator.alloc();
+        let wall = entity_allocator.alloc();
+        spatial_table
+            .update(follower, (Coord::new(0, 0), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(leader, (Coord::new(1, 0), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(blocker, (Coord::new(2, 0), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(rival_a, (Coord::new(4, 4), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(rival_b, (Coord::new(6, 4), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(wall, (Coord::new(5, 4), Layer::Feature).into())
+            .unwrap();
+
+        // the leader is blocked, so the follower can't move into the leader's cell
+        let conflicts = spatial_table.update_coords_simultaneous(vec![
+            (follower, Coord::new(1, 0)),
+            (leader, Coord::new(2, 0)),
+            (rival_a, Coord::new(5, 4)),
+            (rival_b, Coord::new(5, 4)),
+        ]);
+        assert_eq!(
+            conflicts,
+            vec![
+                MoveConflict::Contested {
+                    entity: rival_a,
+                    rival: rival_b,
+                },
+                MoveConflict::Contested {
+                    entity: rival_b,
+                    rival: rival_a,
+                },
+                MoveConflict::OccupiedBy {
+                    entity: leader,
+                    occupant: blocker,
+                },
+                MoveConflict::OccupiedBy {
+                    entity: follower,
+                    occupant: leader,
+                },
+            ],
+        );
+        assert_eq!(spatial_table.coord_of(follower), Some(Coord::new(0, 0)));
+        assert_eq!(spatial_table.coord_of(leader), Some(Coord::new(1, 0)));
+        assert_eq!(spatial_table.coord_of(rival_a), Some(Coord::new(4, 4)));
+
+        // entities on different layers don't contest the same cell
+        let conflicts = spatial_table.update_coords_simultaneous(vec![
+            (rival_a, Coord::new(5, 5)),
+            (wall, Coord::new(5, 5)),
+        ]);
+        assert_eq!(conflicts, vec![]);
+        assert_eq!(
+            *spatial_table.layers_at_checked(Coord::new(5, 5)),
+            Layers {
+                feature: Some(wall),
+                character: Some(rival_a),
+            },
+        );
+    }
+}
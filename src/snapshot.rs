@@ -0,0 +1,425 @@
+use crate::{
+    Coord, Entity, Footprint, Layers, Location, SpatialTable, Storage, TransactionConflict,
+};
+use entity_table::ComponentTable;
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// The contents of a stack layer at a coord, from the bottom of the stack to the top
+type StackEntry<L> = ((Coord, L), Vec<Entity>);
+
+/// The locations and footprints of every entity in a [`SpatialTable`] at some point in time,
+/// along with the order of the entities in each stack
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct Snapshot<L> {
+    locations: ComponentTable<Location<L>>,
+    #[cfg_attr(feature = "serialize", serde(default))]
+    footprints: ComponentTable<Footprint>,
+    #[cfg_attr(feature = "serialize", serde(default))]
+    stacks: Vec<StackEntry<L>>,
+}
+
+/// A single difference between two snapshots
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffEntry<L> {
+    /// The entity had no location, and now has a location
+    Added {
+        entity: Entity,
+        location: Location<L>,
+    },
+    /// The entity's coord changed
+    Moved { entity: Entity, coord: Coord },
+    /// The entity's layer changed
+    LayerChanged { entity: Entity, layer: Option<L> },
+    /// The entity had a location, and now has no location
+    Removed { entity: Entity },
+}
+
+/// The differences between two snapshots. Entities whose location and footprint are the same
+/// in both snapshots don't appear in the diff.
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diff<L> {
+    entries: Vec<DiffEntry<L>>,
+    #[cfg_attr(feature = "serialize", serde(default))]
+    footprints: Vec<(Entity, Footprint)>,
+    #[cfg_attr(feature = "serialize", serde(default))]
+    stacks: Vec<StackEntry<L>>,
+}
+
+impl<L> Diff<L> {
+    pub fn entries(&self) -> &[DiffEntry<L>] {
+        &self.entries
+    }
+    /// The new footprint of each entity whose footprint changed. A footprint is cleared by
+    /// changing it to [`Footprint::single`].
+    pub fn footprints(&self) -> &[(Entity, Footprint)] {
+        &self.footprints
+    }
+    /// The new order of each stack whose contents changed, from the bottom of the stack to the
+    /// top
+    pub fn stacks(&self) -> &[StackEntry<L>] {
+        &self.stacks
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty() && self.footprints.is_empty() && self.stacks.is_empty()
+    }
+}
+
+impl<L: Copy + Eq + Hash> Snapshot<L> {
+    pub fn location_of(&self, entity: Entity) -> Option<&Location<L>> {
+        self.locations.get(entity)
+    }
+    /// The footprint of an entity, or `None` if it covers only its anchor
+    pub fn footprint_of(&self, entity: Entity) -> Option<&Footprint> {
+        self.footprints.get(entity)
+    }
+    /// Computes the changes that turn `self` into `to`
+    pub fn diff(&self, to: &Self) -> Diff<L> {
+        let mut entries = Vec::new();
+        for (entity, &location) in to.locations.iter() {
+            match self.locations.get(entity) {
+                None => entries.push(DiffEntry::Added { entity, location }),
+                Some(original_location) => {
+                    if original_location.coord != location.coord {
+                        entries.push(DiffEntry::Moved {
+                            entity,
+                            coord: location.coord,
+                        });
+                    }
+                    if original_location.layer != location.layer {
+                        entries.push(DiffEntry::LayerChanged {
+                            entity,
+                            layer: location.layer,
+                        });
+                    }
+                }
+            }
+        }
+        for entity in self.locations.entities() {
+            if !to.locations.contains(entity) {
+                entries.push(DiffEntry::Removed { entity });
+            }
+        }
+        let mut footprints = Vec::new();
+        for (entity, footprint) in to.footprints.iter() {
+            if self.footprints.get(entity) != Some(footprint) {
+                footprints.push((entity, footprint.clone()));
+            }
+        }
+        for entity in self.footprints.entities() {
+            // removing an entity discards its footprint
+            let removed = self.locations.contains(entity) && !to.locations.contains(entity);
+            if !to.footprints.contains(entity) && !removed {
+                footprints.push((entity, Footprint::single()));
+            }
+        }
+        let original_stacks = self
+            .stacks
+            .iter()
+            .map(|(key, stack)| (*key, stack))
+            .collect::<HashMap<_, _>>();
+        let stacks = to
+            .stacks
+            .iter()
+            .filter(|(key, stack)| original_stacks.get(key) != Some(&stack))
+            .cloned()
+            .collect();
+        Diff {
+            entries,
+            footprints,
+            stacks,
+        }
+    }
+}
+
+impl<L: Layers, S: Storage<L>> SpatialTable<L, S> {
+    /// Copies the location and footprint of every entity, and the order of every stack. This
+    /// takes time and memory proportional to the number of entities in the table.
+    pub fn snapshot(&self) -> Snapshot<L::Layer> {
+        Snapshot {
+            locations: self.location_component.clone(),
+            footprints: self.footprints.clone(),
+            stacks: self
+                .stacks
+                .iter()
+                .map(|(&key, stack)| (key, stack.clone()))
+                .collect(),
+        }
+    }
+    /// Applies every entry of a diff as a single transaction. The diff needn't have been
+    /// computed from this table. If the result would contain conflicts, the table is left
+    /// unchanged and the conflicts are returned. Once the transaction is committed, each stack
+    /// in the diff which contains the same entities in this table is put in the order it has in
+    /// the diff.
+    pub fn apply_diff(&mut self, diff: &Diff<L::Layer>) -> Result<(), Vec<TransactionConflict>> {
+        let mut transaction = self.transaction();
+        for &entry in diff.entries() {
+            match entry {
+                DiffEntry::Added { entity, location } => transaction.update(entity, location),
+                DiffEntry::Moved { entity, coord } => transaction.update_coord(entity, coord),
+                DiffEntry::LayerChanged {
+                    entity,
+                    layer: Some(layer),
+                } => transaction.update_layer(entity, layer),
+                DiffEntry::LayerChanged {
+                    entity,
+                    layer: None,
+                } => transaction.clear_layer(entity),
+                DiffEntry::Removed { entity } => transaction.remove(entity),
+            }
+        }
+        for (entity, footprint) in diff.footprints() {
+            transaction.set_footprint(*entity, footprint.clone());
+        }
+        transaction.commit()?;
+        for ((coord, layer), order) in diff.stacks() {
+            self.reorder_stack(*coord, *layer, order);
+        }
+        Ok(())
+    }
+    /// Puts the stack at a coord in the given order, if it contains the same entities
+    fn reorder_stack(&mut self, coord: Coord, layer: L::Layer, order: &[Entity]) {
+        let stack = match self.stacks.get_mut(&(coord, layer)) {
+            Some(stack) => stack,
+            None => return,
+        };
+        if stack.len() != order.len() || !order.iter().all(|entity| stack.contains(entity)) {
+            return;
+        }
+        stack.copy_from_slice(order);
+        if let Some(cell) = self.spatial_grid.get_mut(coord) {
+            *cell.select_field_mut(layer) = order.last().copied();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    crate::declare_layers_module! {
+        layers {
+            feature: Feature,
+            character: Character,
+        }
+    }
+    use super::DiffEntry;
+    use crate::{Coord, Footprint, Size, TransactionConflict};
+    use entity_table::EntityAllocator;
+    use layers::{Layer, Layers};
+    type SpatialTable = crate::SpatialTable<Layers>;
+
+    #[test]
+    fn diff_and_apply() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let hero = entity_allocator.alloc();
+        let wall = entity_allocator.alloc();
+        let door = entity_allocator.alloc();
+        let item = entity_allocator.alloc();
+        spatial_table
+            .update(hero, (Coord::new(1, 1), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(wall, (Coord::new(2, 2), Layer::Feature).into())
+            .unwrap();
+        spatial_table
+            .update(door, (Coord::new(3, 3), Layer::Feature).into())
+            .unwrap();
+        let mut replica = SpatialTable::new(Size::new(10, 10));
+        replica
+            .apply_diff(&replica.snapshot().diff(&spatial_table.snapshot()))
+            .unwrap();
+        let before = spatial_table.snapshot();
+
+        spatial_table.update_coord(hero, Coord::new(2, 2)).unwrap();
+        spatial_table
+            .update_layer(hero, Layer::Feature)
+            .unwrap_err();
+        spatial_table.remove(wall);
+        spatial_table.update_layer(hero, Layer::Feature).unwrap();
+        spatial_table.clear_layer(door).unwrap();
+        spatial_table
+            .update(item, (Coord::new(4, 4), Layer::Character).into())
+            .unwrap();
+        let diff = before.diff(&spatial_table.snapshot());
+        assert_eq!(
+            diff.entries(),
+            &[
+                DiffEntry::Moved {
+                    entity: hero,
+                    coord: Coord::new(2, 2),
+                },
+                DiffEntry::LayerChanged {
+                    entity: hero,
+                    layer: Some(Layer::Feature),
+                },
+                DiffEntry::LayerChanged {
+                    entity: door,
+                    layer: None,
+                },
+                DiffEntry::Added {
+                    entity: item,
+                    location: (Coord::new(4, 4), Layer::Character).into(),
+                },
+                DiffEntry::Removed { entity: wall },
+            ],
+        );
+
+        // the hero moves into the wall's cell, which is only possible because the wall is
+        // removed in the same diff
+        replica.apply_diff(&diff).unwrap();
+        assert!(replica
+            .snapshot()
+            .diff(&spatial_table.snapshot())
+            .is_empty());
+        assert_eq!(
+            *replica.layers_at_checked(Coord::new(2, 2)),
+            Layers {
+                feature: Some(hero),
+                character: None,
+            },
+        );
+    }
+
+    #[test]
+    fn footprints() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let boss = entity_allocator.alloc();
+        let hero = entity_allocator.alloc();
+        let statue = entity_allocator.alloc();
+        spatial_table
+            .set_footprint(boss, Footprint::rect(Size::new(2, 2)))
+            .unwrap();
+        spatial_table
+            .update(boss, (Coord::new(1, 1), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .set_footprint(statue, Footprint::rect(Size::new(1, 2)))
+            .unwrap();
+        spatial_table
+            .update(statue, (Coord::new(5, 5), Layer::Feature).into())
+            .unwrap();
+        let mut replica = SpatialTable::new(Size::new(10, 10));
+        replica
+            .apply_diff(&replica.snapshot().diff(&spatial_table.snapshot()))
+            .unwrap();
+        assert_eq!(
+            replica.entity_at(Coord::new(2, 2), Layer::Character),
+            Some(boss)
+        );
+        let before = spatial_table.snapshot();
+        assert_eq!(
+            before.footprint_of(boss),
+            Some(&Footprint::rect(Size::new(2, 2)))
+        );
+
+        // the boss grows into a cell vacated by the hero
+        spatial_table
+            .update(hero, (Coord::new(3, 1), Layer::Character).into())
+            .unwrap();
+        let with_hero = spatial_table.snapshot();
+        replica.apply_diff(&before.diff(&with_hero)).unwrap();
+        spatial_table.remove(hero);
+        spatial_table
+            .set_footprint(boss, Footprint::rect(Size::new(3, 2)))
+            .unwrap();
+        spatial_table.clear_footprint(statue);
+        spatial_table.remove(statue);
+        let diff = with_hero.diff(&spatial_table.snapshot());
+        assert_eq!(
+            diff.footprints(),
+            &[(boss, Footprint::rect(Size::new(3, 2)))]
+        );
+        replica.apply_diff(&diff).unwrap();
+        assert!(replica
+            .snapshot()
+            .diff(&spatial_table.snapshot())
+            .is_empty());
+        assert_eq!(
+            replica.entity_at(Coord::new(3, 2), Layer::Character),
+            Some(boss)
+        );
+        assert_eq!(replica.footprint_of(statue), None);
+        assert_eq!(replica.check_invariants(), vec![]);
+
+        // a footprint that doesn't fit leaves the replica unchanged
+        spatial_table
+            .update(hero, (Coord::new(4, 1), Layer::Character).into())
+            .unwrap();
+        let mut diff = replica.snapshot().diff(&spatial_table.snapshot());
+        diff.footprints
+            .push((boss, Footprint::rect(Size::new(4, 1))));
+        assert_eq!(
+            replica.apply_diff(&diff),
+            Err(vec![TransactionConflict::OccupiedBy {
+                entity: boss,
+                occupant: hero,
+            }]),
+        );
+        assert_eq!(
+            replica.footprint_of(boss),
+            Some(&Footprint::rect(Size::new(3, 2)))
+        );
+        assert_eq!(replica.coord_of(hero), None);
+        assert_eq!(replica.check_invariants(), vec![]);
+    }
+
+    mod stack {
+        crate::declare_layers_module! {
+            layers {
+                item: Item [stack],
+            }
+        }
+        use crate::{Coord, Size};
+        use entity_table::EntityAllocator;
+        use layers::{Layer, Layers};
+        type SpatialTable = crate::SpatialTable<Layers>;
+
+        #[test]
+        fn stack_order() {
+            let mut entity_allocator = EntityAllocator::default();
+            let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+            let item_a = entity_allocator.alloc();
+            let item_b = entity_allocator.alloc();
+            let item_c = entity_allocator.alloc();
+            let coord = Coord::new(1, 1);
+            for &item in &[item_c, item_a, item_b] {
+                spatial_table
+                    .update(item, (coord, Layer::Item).into())
+                    .unwrap();
+            }
+            let mut replica = SpatialTable::new(Size::new(10, 10));
+            replica
+                .apply_diff(&replica.snapshot().diff(&spatial_table.snapshot()))
+                .unwrap();
+            let stack_at = |spatial_table: &SpatialTable| {
+                spatial_table
+                    .stack_at(coord, Layer::Item)
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(stack_at(&replica), vec![item_c, item_a, item_b]);
+
+            // moving an item out of the stack and back changes only the order of the stack
+            let before = spatial_table.snapshot();
+            spatial_table
+                .update_coord(item_a, Coord::new(2, 2))
+                .unwrap();
+            spatial_table.update_coord(item_a, coord).unwrap();
+            let diff = before.diff(&spatial_table.snapshot());
+            assert_eq!(diff.entries(), &[]);
+            assert!(!diff.is_empty());
+            replica.apply_diff(&diff).unwrap();
+            assert_eq!(stack_at(&replica), vec![item_c, item_b, item_a]);
+            assert_eq!(replica.layers_at_checked(coord).item, Some(item_a));
+            assert!(replica
+                .snapshot()
+                .diff(&spatial_table.snapshot())
+                .is_empty());
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use crate::{Coord, Layers, Size};
+use grid_2d::Grid;
+use std::collections::HashMap;
+
+/// The cells of a [`crate::SpatialTable`], indexed by coord
+pub trait Storage<L: Layers> {
+    /// Returns the cell at a coord, or `None` if the coord is outside the bounds of the storage
+    fn get(&self, coord: Coord) -> Option<&L>;
+    /// Returns the cell at a coord for modification, or `None` if the coord is outside the
+    /// bounds of the storage. This never allocates.
+    fn get_mut(&mut self, coord: Coord) -> Option<&mut L>;
+    /// Like [`Self::get_mut`], but allocates storage for the cell if necessary. Storage
+    /// allocated by this method is only retained if it's followed by a call to
+    /// [`Self::occupy`].
+    fn get_mut_or_alloc(&mut self, coord: Coord) -> Option<&mut L> {
+        self.get_mut(coord)
+    }
+    /// Called after an entity is added to the cell at a coord
+    fn occupy(&mut self, _coord: Coord) {}
+    /// Called after an entity is removed from the cell at a coord
+    fn vacate(&mut self, _coord: Coord) {}
+    /// Removes every entity from every cell
+    fn clear(&mut self);
+    /// The size of the region which can contain entities, starting at the origin, or `None` if
+    /// the storage is unbounded
+    fn bounds(&self) -> Option<Size>;
+    /// Calls `f` on every allocated cell along with its coord
+    fn for_each_cell<F: FnMut(Coord, &L)>(&self, f: F);
+    /// Rectangles, as top-left coords and sizes, which together cover every allocated cell, or
+    /// `None` if every cell within [`Self::bounds`] is allocated. Region queries only visit
+    /// cells within these rectangles.
+    fn allocated_regions(&self) -> Option<Vec<(Coord, Size)>> {
+        None
+    }
+}
+
+impl<L: Layers> Storage<L> for Grid<L> {
+    fn get(&self, coord: Coord) -> Option<&L> {
+        Grid::get(self, coord)
+    }
+    fn get_mut(&mut self, coord: Coord) -> Option<&mut L> {
+        Grid::get_mut(self, coord)
+    }
+    fn clear(&mut self) {
+        for cell in self.iter_mut() {
+            *cell = Default::default();
+        }
+    }
+    fn bounds(&self) -> Option<Size> {
+        Some(self.size())
+    }
+    fn for_each_cell<F: FnMut(Coord, &L)>(&self, mut f: F) {
+        for (coord, cell) in self.enumerate() {
+            f(coord, cell);
+        }
+    }
+}
+
+/// The width and height of each chunk of a [`Chunked`] storage
+pub const CHUNK_SIZE: u32 = 16;
+
+#[derive(Debug)]
+struct Chunk<L> {
+    cells: Grid<L>,
+    num_entities: usize,
+}
+
+/// Unbounded storage which divides the plane into square chunks, and only allocates the chunks
+/// which contain entities. A chunk is freed as soon as its last entity is removed.
+#[derive(Debug)]
+pub struct Chunked<L> {
+    chunks: HashMap<Coord, Chunk<L>>,
+    empty: L,
+}
+
+impl<L: Layers> Default for Chunked<L> {
+    fn default() -> Self {
+        Self {
+            chunks: HashMap::new(),
+            empty: L::default(),
+        }
+    }
+}
+
+/// Returns the coord of the chunk containing a coord, and the offset of the coord within the
+/// chunk
+fn split_coord(coord: Coord) -> (Coord, Coord) {
+    let chunk_size = CHUNK_SIZE as i32;
+    let chunk_coord = Coord::new(
+        coord.x.div_euclid(chunk_size),
+        coord.y.div_euclid(chunk_size),
+    );
+    let offset = Coord::new(
+        coord.x.rem_euclid(chunk_size),
+        coord.y.rem_euclid(chunk_size),
+    );
+    (chunk_coord, offset)
+}
+
+impl<L> Chunked<L> {
+    pub fn num_chunks(&self) -> usize {
+        self.chunks.len()
+    }
+}
+
+impl<L: Layers> Storage<L> for Chunked<L> {
+    fn get(&self, coord: Coord) -> Option<&L> {
+        let (chunk_coord, offset) = split_coord(coord);
+        match self.chunks.get(&chunk_coord) {
+            Some(chunk) => Some(chunk.cells.get_checked(offset)),
+            None => Some(&self.empty),
+        }
+    }
+    fn get_mut(&mut self, coord: Coord) -> Option<&mut L> {
+        let (chunk_coord, offset) = split_coord(coord);
+        self.chunks
+            .get_mut(&chunk_coord)
+            .map(|chunk| chunk.cells.get_checked_mut(offset))
+    }
+    fn get_mut_or_alloc(&mut self, coord: Coord) -> Option<&mut L> {
+        let (chunk_coord, offset) = split_coord(coord);
+        let chunk = self.chunks.entry(chunk_coord).or_insert_with(|| Chunk {
+            cells: Grid::new_default(Size::new(CHUNK_SIZE, CHUNK_SIZE)),
+            num_entities: 0,
+        });
+        Some(chunk.cells.get_checked_mut(offset))
+    }
+    fn occupy(&mut self, coord: Coord) {
+        let (chunk_coord, _) = split_coord(coord);
+        if let Some(chunk) = self.chunks.get_mut(&chunk_coord) {
+            chunk.num_entities += 1;
+        }
+    }
+    fn vacate(&mut self, coord: Coord) {
+        let (chunk_coord, _) = split_coord(coord);
+        if let Some(chunk) = self.chunks.get_mut(&chunk_coord) {
+            chunk.num_entities -= 1;
+            if chunk.num_entities == 0 {
+                self.chunks.remove(&chunk_coord);
+            }
+        }
+    }
+    fn clear(&mut self) {
+        self.chunks.clear();
+    }
+    fn bounds(&self) -> Option<Size> {
+        None
+    }
+    fn for_each_cell<F: FnMut(Coord, &L)>(&self, mut f: F) {
+        for (&chunk_coord, chunk) in self.chunks.iter() {
+            let top_left = chunk_top_left(chunk_coord);
+            for (offset, cell) in chunk.cells.enumerate() {
+                f(top_left + offset, cell);
+            }
+        }
+    }
+    fn allocated_regions(&self) -> Option<Vec<(Coord, Size)>> {
+        let chunk_size = Size::new(CHUNK_SIZE, CHUNK_SIZE);
+        Some(
+            self.chunks
+                .keys()
+                .map(|&chunk_coord| (chunk_top_left(chunk_coord), chunk_size))
+                .collect(),
+        )
+    }
+}
+
+/// Returns the coord of the top-left cell of a chunk
+fn chunk_top_left(chunk_coord: Coord) -> Coord {
+    let chunk_size = CHUNK_SIZE as i32;
+    Coord::new(chunk_coord.x * chunk_size, chunk_coord.y * chunk_size)
+}
+
+#[cfg(test)]
+mod test {
+    crate::declare_layers_module! {
+        layers {
+            feature: Feature,
+            item: Item [stack],
+            character: Character,
+        }
+    }
+    use crate::{Coord, Metric, Size, UpdateError};
+    use entity_table::EntityAllocator;
+    use layers::{Layer, Layers};
+    type ChunkedSpatialTable = crate::ChunkedSpatialTable<Layers>;
+
+    #[test]
+    fn negative_coords_and_chunk_freeing() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = ChunkedSpatialTable::new_chunked();
+        let hero = entity_allocator.alloc();
+        let villain = entity_allocator.alloc();
+        let item = entity_allocator.alloc();
+        let far = Coord::new(-1000, 5000);
+        spatial_table
+            .update(hero, (Coord::new(-1, -1), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(item, (Coord::new(-1, -1), Layer::Item).into())
+            .unwrap();
+        spatial_table
+            .update(villain, (far, Layer::Character).into())
+            .unwrap();
+        assert_eq!(spatial_table.num_chunks(), 2);
+        assert_eq!(
+            spatial_table
+                .layers_at(Coord::new(-1, -1))
+                .unwrap()
+                .character,
+            Some(hero),
+        );
+        assert_eq!(
+            spatial_table
+                .layers_at(Coord::new(0, 0))
+                .map(|layers| *layers.layers()),
+            Some(Layers::default())
+        );
+        assert_eq!(
+            spatial_table.update(item, (far, Layer::Character).into()),
+            Err(UpdateError::OccupiedBy(villain)),
+        );
+        assert_eq!(
+            spatial_table
+                .entities_in_rect(Coord::new(-2, -2), Size::new(3, 3), None)
+                .map(|(coord, _, entity)| (coord, entity))
+                .collect::<Vec<_>>(),
+            vec![(Coord::new(-1, -1), item), (Coord::new(-1, -1), hero)],
+        );
+
+        // the chunk containing (-1, -1) is only freed once both entities leave it
+        spatial_table.update_coord(hero, Coord::new(0, 0)).unwrap();
+        assert_eq!(spatial_table.num_chunks(), 3);
+        spatial_table.clear_layer(item).unwrap();
+        assert_eq!(spatial_table.num_chunks(), 2);
+        assert_eq!(spatial_table.coord_of(item), Some(Coord::new(-1, -1)));
+
+        spatial_table
+            .update_coord(villain, Coord::new(15, 15))
+            .unwrap();
+        assert_eq!(spatial_table.num_chunks(), 1);
+        spatial_table.remove(hero);
+        spatial_table.remove(villain);
+        assert_eq!(spatial_table.num_chunks(), 0);
+        assert_eq!(spatial_table.iter_entities().count(), 1);
+    }
+
+    #[test]
+    fn region_queries_skip_unallocated_chunks() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = ChunkedSpatialTable::new_chunked();
+        let hero = entity_allocator.alloc();
+        let villain = entity_allocator.alloc();
+        spatial_table
+            .update(hero, (Coord::new(-1_000_000, 20), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(
+                villain,
+                (Coord::new(1_000_000, -20), Layer::Character).into(),
+            )
+            .unwrap();
+        assert_eq!(
+            spatial_table
+                .entities_in_rect(
+                    Coord::new(-(1 << 30), -(1 << 30)),
+                    Size::new(1 << 31, 1 << 31),
+                    None,
+                )
+                .map(|(_, _, entity)| entity)
+                .collect::<Vec<_>>(),
+            vec![villain, hero],
+        );
+        assert_eq!(
+            spatial_table
+                .entities_in_radius(Coord::new(0, 0), 1_000_000, Metric::Manhattan, None)
+                .count(),
+            0,
+        );
+        assert_eq!(
+            spatial_table
+                .entities_in_radius(Coord::new(0, 0), u32::MAX, Metric::Chebyshev, None)
+                .map(|(coord, _, _)| coord)
+                .collect::<Vec<_>>(),
+            vec![Coord::new(1_000_000, -20), Coord::new(-1_000_000, 20)],
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use crate::{Coord, Entity, Layers, Size, SpatialTable, UpdateError};
+
+/// A reason [`SpatialTable::from_text`] couldn't build a table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FromTextError {
+    /// Two entities were placed on the same layer of the same cell
+    OccupiedBy {
+        entity: Entity,
+        occupant: Entity,
+        coord: Coord,
+    },
+    /// The same entity was returned for two cells, or twice for one cell
+    EntityReused { entity: Entity, coord: Coord },
+    /// The entity couldn't be placed inside the spatial grid
+    OutOfBounds { entity: Entity, coord: Coord },
+}
+
+impl<L: Layers> SpatialTable<L> {
+    /// Renders the spatial grid as text with one line per row. Each cell is drawn with the
+    /// character returned by `f`.
+    pub fn to_text<F: FnMut(Coord, &L) -> char>(&self, mut f: F) -> String {
+        let size = self.grid_size();
+        let mut text = String::with_capacity(((size.width() + 1) * size.height()) as usize);
+        for (coord, cell) in self.enumerate() {
+            text.push(f(coord, cell));
+            if coord.x as u32 == size.width() - 1 {
+                text.push('\n');
+            }
+        }
+        text
+    }
+    /// Builds a spatial table from text with one line per row. The grid is as wide as the
+    /// longest line, and shorter lines are treated as though they were padded with spaces. `f`
+    /// is called with each character and its coord, and returns the layer and entity of each
+    /// entity to place in that cell. Typically `f` allocates a new entity for each character, and
+    /// returning an entity which has already been placed is an error.
+    pub fn from_text<F, I>(text: &str, mut f: F) -> Result<Self, FromTextError>
+    where
+        F: FnMut(char, Coord) -> I,
+        I: IntoIterator<Item = (L::Layer, Entity)>,
+    {
+        let width = text
+            .lines()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0);
+        let height = text.lines().count();
+        let mut spatial_table = Self::new(Size::new(width as u32, height as u32));
+        for (y, line) in text.lines().enumerate() {
+            let mut chars = line.chars();
+            for x in 0..width {
+                let coord = Coord::new(x as i32, y as i32);
+                let ch = chars.next().unwrap_or(' ');
+                for (layer, entity) in f(ch, coord) {
+                    if spatial_table.location_of(entity).is_some() {
+                        return Err(FromTextError::EntityReused { entity, coord });
+                    }
+                    match spatial_table.update(entity, (coord, layer).into()) {
+                        Ok(()) => (),
+                        Err(UpdateError::OccupiedBy(occupant)) => {
+                            return Err(FromTextError::OccupiedBy {
+                                entity,
+                                occupant,
+                                coord,
+                            })
+                        }
+                        Err(UpdateError::DestinationOutOfBounds) => {
+                            return Err(FromTextError::OutOfBounds { entity, coord })
+                        }
+                    }
+                }
+            }
+        }
+        Ok(spatial_table)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    crate::declare_layers_module! {
+        layers {
+            floor: Floor,
+            feature: Feature,
+            character: Character,
+        }
+    }
+    use super::FromTextError;
+    use crate::Coord;
+    use entity_table::EntityAllocator;
+    use layers::{Layer, Layers};
+    type SpatialTable = crate::SpatialTable<Layers>;
+
+    #[test]
+    fn text_round_trip() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut hero = None;
+        let text = "\
+#####
+#.@.#
+#..
+#####
+";
+        let spatial_table = SpatialTable::from_text(text, |ch, _| {
+            let mut entity = || entity_allocator.alloc();
+            match ch {
+                '#' => vec![(Layer::Feature, entity())],
+                '.' => vec![(Layer::Floor, entity())],
+                '@' => {
+                    let entity = entity();
+                    hero = Some(entity);
+                    vec![
+                        (Layer::Floor, entity_allocator.alloc()),
+                        (Layer::Character, entity),
+                    ]
+                }
+                _ => vec![],
+            }
+        })
+        .unwrap();
+        let hero = hero.unwrap();
+        assert_eq!(spatial_table.coord_of(hero), Some(Coord::new(2, 1)));
+        assert!(spatial_table
+            .layers_at_checked(Coord::new(2, 1))
+            .floor
+            .is_some());
+
+        let render = |_, cell: &Layers| {
+            if cell.character.is_some() {
+                '@'
+            } else if cell.feature.is_some() {
+                '#'
+            } else if cell.floor.is_some() {
+                '.'
+            } else {
+                ' '
+            }
+        };
+        assert_eq!(
+            spatial_table.to_text(render),
+            "#####\n#.@.#\n#..  \n#####\n"
+        );
+
+        let mut entity_allocator = EntityAllocator::default();
+        let entity = entity_allocator.alloc();
+        let occupant = entity_allocator.alloc();
+        assert_eq!(
+            SpatialTable::from_text("..", |_, _| vec![
+                (Layer::Floor, occupant),
+                (Layer::Floor, entity)
+            ])
+            .unwrap_err(),
+            FromTextError::OccupiedBy {
+                entity,
+                occupant,
+                coord: Coord::new(0, 0),
+            },
+        );
+        assert_eq!(
+            SpatialTable::from_text(".\n.", |_, _| vec![(Layer::Floor, entity)]).unwrap_err(),
+            FromTextError::EntityReused {
+                entity,
+                coord: Coord::new(0, 1),
+            },
+        );
+        assert_eq!(
+            SpatialTable::from_text(".", |_, _| vec![
+                (Layer::Floor, entity),
+                (Layer::Character, entity)
+            ])
+            .unwrap_err(),
+            FromTextError::EntityReused {
+                entity,
+                coord: Coord::new(0, 0),
+            },
+        );
+    }
+}
@@ -0,0 +1,250 @@
+use crate::{Coord, Entity, Footprint, Layers, Location, SpatialTable, Storage, UpdateError};
+use grid_2d::Grid;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operation<T> {
+    Update(Location<T>),
+    UpdateCoord(Coord),
+    UpdateLayer(T),
+    ClearLayer,
+    Remove,
+}
+
+/// A reason a [`Transaction`] couldn't be committed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionConflict {
+    /// The entity's final location is occupied by another entity
+    OccupiedBy { entity: Entity, occupant: Entity },
+    /// The entity's final location is outside the bounds of the spatial grid
+    DestinationOutOfBounds { entity: Entity },
+    /// The entity's layer was changed or cleared while it had no location
+    EntityHasNoCoord { entity: Entity },
+}
+
+impl TransactionConflict {
+    pub fn entity(&self) -> Entity {
+        match *self {
+            Self::OccupiedBy { entity, .. }
+            | Self::DestinationOutOfBounds { entity }
+            | Self::EntityHasNoCoord { entity } => entity,
+        }
+    }
+    pub(crate) fn new(entity: Entity, error: UpdateError) -> Self {
+        match error {
+            UpdateError::OccupiedBy(occupant) => Self::OccupiedBy { entity, occupant },
+            UpdateError::DestinationOutOfBounds => Self::DestinationOutOfBounds { entity },
+        }
+    }
+}
+
+/// Buffers a sequence of updates to a [`SpatialTable`] which are applied together by
+/// [`Transaction::commit`]. Only the final location of each entity is checked for conflicts, so
+/// an entity may move into a location which is vacated by another entity in the same
+/// transaction, regardless of the order of the updates. Dropping a transaction without
+/// committing it discards its updates.
+pub struct Transaction<'a, L: Layers, S: Storage<L> = Grid<L>> {
+    spatial_table: &'a mut SpatialTable<L, S>,
+    operations: Vec<(Entity, Operation<L::Layer>)>,
+    footprints: Vec<(Entity, Footprint)>,
+}
+
+impl<L: Layers, S: Storage<L>> SpatialTable<L, S> {
+    pub fn transaction(&mut self) -> Transaction<'_, L, S> {
+        Transaction {
+            spatial_table: self,
+            operations: Vec::new(),
+            footprints: Vec::new(),
+        }
+    }
+}
+
+impl<'a, L: Layers, S: Storage<L>> Transaction<'a, L, S> {
+    pub fn update(&mut self, entity: Entity, location: Location<L::Layer>) {
+        self.operations.push((entity, Operation::Update(location)));
+    }
+    pub fn update_coord(&mut self, entity: Entity, coord: Coord) {
+        self.operations
+            .push((entity, Operation::UpdateCoord(coord)));
+    }
+    pub fn update_layer(&mut self, entity: Entity, layer: L::Layer) {
+        self.operations
+            .push((entity, Operation::UpdateLayer(layer)));
+    }
+    pub fn clear_layer(&mut self, entity: Entity) {
+        self.operations.push((entity, Operation::ClearLayer));
+    }
+    pub fn remove(&mut self, entity: Entity) {
+        self.operations.push((entity, Operation::Remove));
+    }
+    /// Changes the footprint of an entity. The cells of the new footprint are checked for
+    /// conflicts at the entity's final location, like any other update.
+    pub fn set_footprint(&mut self, entity: Entity, footprint: Footprint) {
+        self.footprints.retain(|&(other, _)| other != entity);
+        self.footprints.push((entity, footprint));
+    }
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty() && self.footprints.is_empty()
+    }
+    /// Applies all the buffered updates to the spatial table. If any update conflicts with
+    /// the rest of the table, no updates are applied and every conflict is returned.
+    pub fn commit(self) -> Result<(), Vec<TransactionConflict>> {
+        let Self {
+            spatial_table,
+            operations,
+            footprints,
+        } = self;
+        let mut conflicts = Vec::new();
+        let mut moves: Vec<(Entity, Option<Location<L::Layer>>)> = Vec::new();
+        let mut move_index_by_entity = HashMap::new();
+        for (entity, operation) in operations {
+            let index = *move_index_by_entity.entry(entity).or_insert_with(|| {
+                let location = spatial_table.location_of(entity).copied();
+                moves.push((entity, location));
+                moves.len() - 1
+            });
+            let location = &mut moves[index].1;
+            match operation {
+                Operation::Update(new_location) => *location = Some(new_location),
+                Operation::UpdateCoord(coord) => {
+                    if let Some(location) = location.as_mut() {
+                        location.coord = coord;
+                    } else {
+                        *location = Some(Location { coord, layer: None });
+                    }
+                }
+                Operation::UpdateLayer(layer) => {
+                    if let Some(location) = location.as_mut() {
+                        location.layer = Some(layer);
+                    } else {
+                        conflicts.push(TransactionConflict::EntityHasNoCoord { entity });
+                    }
+                }
+                Operation::ClearLayer => {
+                    if let Some(location) = location.as_mut() {
+                        location.layer = None;
+                    } else {
+                        conflicts.push(TransactionConflict::EntityHasNoCoord { entity });
+                    }
+                }
+                Operation::Remove => *location = None,
+            }
+        }
+        // entities whose footprint changes must be moved even if their location doesn't
+        for &(entity, _) in footprints.iter() {
+            move_index_by_entity.entry(entity).or_insert_with(|| {
+                moves.push((entity, spatial_table.location_of(entity).copied()));
+                moves.len() - 1
+            });
+        }
+        let commit = conflicts.is_empty();
+        let errors = spatial_table.place_all(&moves, commit, &footprints);
+        if commit && errors.is_empty() {
+            return Ok(());
+        }
+        conflicts.extend(
+            errors
+                .into_iter()
+                .map(|(entity, error)| TransactionConflict::new(entity, error)),
+        );
+        Err(conflicts)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    crate::declare_layers_module! {
+        layers {
+            feature: Feature,
+            character: Character,
+        }
+    }
+    use super::TransactionConflict;
+    use crate::{Coord, Size};
+    use entity_table::EntityAllocator;
+    use layers::{Layer, Layers};
+    type SpatialTable = crate::SpatialTable<Layers>;
+
+    #[test]
+    fn vacated_cells_can_be_reused() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let entity_a = entity_allocator.alloc();
+        let entity_b = entity_allocator.alloc();
+        let entity_c = entity_allocator.alloc();
+        spatial_table
+            .update(entity_a, (Coord::new(0, 0), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(entity_b, (Coord::new(1, 0), Layer::Character).into())
+            .unwrap();
+
+        // entity_a moves into the cell vacated by entity_b, even though entity_b moves later
+        let mut transaction = spatial_table.transaction();
+        transaction.update_coord(entity_a, Coord::new(1, 0));
+        transaction.update_coord(entity_b, Coord::new(2, 0));
+        transaction.update(entity_c, (Coord::new(0, 0), Layer::Feature).into());
+        transaction.update_layer(entity_c, Layer::Character);
+        transaction.commit().unwrap();
+
+        assert_eq!(spatial_table.coord_of(entity_a), Some(Coord::new(1, 0)));
+        assert_eq!(spatial_table.coord_of(entity_b), Some(Coord::new(2, 0)));
+        assert_eq!(
+            *spatial_table.layers_at_checked(Coord::new(0, 0)),
+            Layers {
+                feature: None,
+                character: Some(entity_c),
+            },
+        );
+    }
+
+    #[test]
+    fn conflicts_leave_table_untouched() {
+        let mut entity_allocator = EntityAllocator::default();
+        let mut spatial_table = SpatialTable::new(Size::new(10, 10));
+        let entity_a = entity_allocator.alloc();
+        let entity_b = entity_allocator.alloc();
+        let entity_c = entity_allocator.alloc();
+        let entity_d = entity_allocator.alloc();
+        spatial_table
+            .update(entity_a, (Coord::new(0, 0), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(entity_b, (Coord::new(1, 0), Layer::Character).into())
+            .unwrap();
+        spatial_table
+            .update(entity_c, (Coord::new(2, 0), Layer::Character).into())
+            .unwrap();
+
+        let mut transaction = spatial_table.transaction();
+        transaction.update_coord(entity_a, Coord::new(5, 5));
+        transaction.update_coord(entity_a, Coord::new(10, 0));
+        transaction.update_coord(entity_b, Coord::new(2, 0));
+        transaction.update(entity_d, (Coord::new(0, 0), Layer::Character).into());
+        transaction.remove(entity_d);
+        transaction.clear_layer(entity_d);
+        assert_eq!(
+            transaction.commit(),
+            Err(vec![
+                TransactionConflict::EntityHasNoCoord { entity: entity_d },
+                TransactionConflict::DestinationOutOfBounds { entity: entity_a },
+                TransactionConflict::OccupiedBy {
+                    entity: entity_b,
+                    occupant: entity_c,
+                },
+            ]),
+        );
+
+        assert_eq!(spatial_table.coord_of(entity_a), Some(Coord::new(0, 0)));
+        assert_eq!(spatial_table.coord_of(entity_b), Some(Coord::new(1, 0)));
+        assert_eq!(spatial_table.coord_of(entity_d), None);
+        assert_eq!(
+            spatial_table.layers_at_checked(Coord::new(0, 0)).character,
+            Some(entity_a),
+        );
+        assert_eq!(
+            spatial_table.layers_at_checked(Coord::new(1, 0)).character,
+            Some(entity_b),
+        );
+    }
+}
@@ -0,0 +1,141 @@
+#![cfg(feature = "derive")]
+
+mod world {
+    pub mod layers {
+        use spatial_table::{Entity, Layers};
+
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Layers)]
+        #[layers(
+            layer = WorldLayer,
+            table = WorldLayerTable,
+            set = WorldLayerSet,
+            derive(PartialOrd, Ord)
+        )]
+        #[cfg_attr(feature = "serialize", layers(serde))]
+        #[cfg_attr(
+            feature = "serialize",
+            derive(spatial_table::serde::Serialize, spatial_table::serde::Deserialize)
+        )]
+        pub(crate) struct WorldLayers {
+            /// The ground
+            pub floor: Option<Entity>,
+            /// Items which can be picked up
+            #[layer(stack)]
+            pub item: Option<Entity>,
+            #[layer(variant = Npc)]
+            pub character: Option<Entity>,
+            pub door_frame: Option<Entity>,
+        }
+    }
+}
+
+use entity_table::EntityAllocator;
+use spatial_table::{Coord, Entity, Layers, ParseLayerError, Size};
+use world::layers::{WorldLayer, WorldLayerSet, WorldLayerTable, WorldLayers};
+type SpatialTable = spatial_table::SpatialTable<WorldLayers>;
+
+#[test]
+fn derived_layers() {
+    assert_eq!(WorldLayer::COUNT, 4);
+    assert_eq!(
+        WorldLayers::LAYERS,
+        &[
+            WorldLayer::Floor,
+            WorldLayer::Item,
+            WorldLayer::Npc,
+            WorldLayer::DoorFrame,
+        ]
+    );
+    assert!(WorldLayer::Floor < WorldLayer::DoorFrame);
+    assert!(WorldLayers::is_stack(WorldLayer::Item));
+    assert!(!WorldLayers::is_stack(WorldLayer::Npc));
+    assert_eq!(WorldLayer::DoorFrame.to_string(), "DoorFrame");
+    assert_eq!("Npc".parse(), Ok(WorldLayer::Npc));
+    assert_eq!(
+        "Character".parse::<WorldLayer>(),
+        Err(ParseLayerError("Character".to_string()))
+    );
+    assert_eq!(WorldLayer::from_index(2), Some(WorldLayer::Npc));
+
+    let mut entity_allocator = EntityAllocator::default();
+    let mut spatial_table = SpatialTable::new(Size::new(4, 4));
+    let hero = entity_allocator.alloc();
+    let coins = entity_allocator.alloc();
+    let sword = entity_allocator.alloc();
+    let coord = Coord::new(1, 2);
+    spatial_table
+        .update(hero, (coord, WorldLayer::Npc).into())
+        .unwrap();
+    spatial_table
+        .update(coins, (coord, WorldLayer::Item).into())
+        .unwrap();
+    spatial_table
+        .update(sword, (coord, WorldLayer::Item).into())
+        .unwrap();
+    assert_eq!(
+        *spatial_table.layers_at_checked(coord),
+        WorldLayers {
+            floor: None,
+            item: Some(sword),
+            character: Some(hero),
+            door_frame: None,
+        }
+    );
+    assert!(spatial_table
+        .stack_at(coord, WorldLayer::Item)
+        .eq([coins, sword]));
+
+    let occupied = *spatial_table.layers_at_checked(coord);
+    let table: WorldLayerTable<bool> = WorldLayerTable {
+        floor: occupied.floor.is_some(),
+        item: occupied.item.is_some(),
+        character: occupied.character.is_some(),
+        door_frame: occupied.door_frame.is_some(),
+    };
+    let mut layers = Vec::new();
+    table.for_each_enumerate(|&occupied, layer| {
+        if occupied {
+            layers.push(layer);
+        }
+    });
+    assert_eq!(layers, vec![WorldLayer::Item, WorldLayer::Npc]);
+    assert!(table.map(|&b| !b).floor);
+
+    let occupied: WorldLayerTable<Option<Entity>> = WorldLayerTable {
+        floor: occupied.floor,
+        item: occupied.item,
+        character: occupied.character,
+        door_frame: occupied.door_frame,
+    };
+    let mut entities = Vec::new();
+    occupied.option_for_each_enumerate(|&entity, layer| entities.push((entity, layer)));
+    assert_eq!(
+        entities,
+        vec![(sword, WorldLayer::Item), (hero, WorldLayer::Npc)]
+    );
+    let is_hero = occupied.option_map(|&entity| entity == hero);
+    assert_eq!(is_hero.character, Some(true));
+    assert_eq!(is_hero.floor, None);
+    let heroes = occupied.option_and_then(|&entity| (entity == hero).then_some(entity));
+    let mut count = 0;
+    heroes.option_for_each(|_| count += 1);
+    assert_eq!(count, 1);
+
+    let blocking: WorldLayerSet = WorldLayer::Npc | WorldLayer::DoorFrame;
+    assert!(spatial_table.is_occupied_in(coord, blocking));
+    assert!(!spatial_table.is_occupied_in(coord, blocking - WorldLayer::Npc.into()));
+}
+
+#[cfg(feature = "serialize")]
+#[test]
+fn derived_serde() {
+    assert_eq!(
+        serde_json::to_string(&WorldLayer::DoorFrame).unwrap(),
+        "\"DoorFrame\""
+    );
+    assert_eq!(
+        serde_json::from_str::<WorldLayer>("\"Npc\"").unwrap(),
+        WorldLayer::Npc
+    );
+    assert!(serde_json::from_str::<WorldLayer>("\"Wall\"").is_err());
+}